@@ -0,0 +1,89 @@
+#[macro_use]
+extern crate bencher;
+extern crate borealis;
+
+use bencher::Bencher;
+
+use borealis::{Document, Fragment};
+use borealis::dom::Arena;
+use borealis::serializer;
+
+const ARTICLE: &'static str = include_str!("pages/article.html");
+
+fn table(rows: usize, columns: usize) -> String {
+    let mut html = String::from("<!DOCTYPE html><html><body><table>");
+
+    for row in 0..rows {
+        html.push_str("<tr>");
+        for column in 0..columns {
+            html.push_str(&format!("<td class=\"cell\">{}:{}</td>", row, column));
+        }
+        html.push_str("</tr>");
+    }
+
+    html.push_str("</table></body></html>");
+    html
+}
+
+fn nested(depth: usize) -> String {
+    let mut html = String::new();
+
+    for _ in 0..depth {
+        html.push_str("<div class=\"layout\">");
+    }
+
+    html.push_str("leaf");
+
+    for _ in 0..depth {
+        html.push_str("</div>");
+    }
+
+    html
+}
+
+fn bench_parse_article(b: &mut Bencher) {
+    b.iter(|| Document::parse_str(ARTICLE));
+    b.bytes = ARTICLE.len() as u64;
+}
+
+fn bench_parse_article_arena(b: &mut Bencher) {
+    b.iter(|| Arena::parse_document_str(ARTICLE));
+    b.bytes = ARTICLE.len() as u64;
+}
+
+fn bench_parse_large_table(b: &mut Bencher) {
+    let html = table(1000, 10);
+
+    b.iter(|| Document::parse_str(&html));
+    b.bytes = html.len() as u64;
+}
+
+fn bench_parse_large_table_arena(b: &mut Bencher) {
+    let html = table(1000, 10);
+
+    b.iter(|| Arena::parse_document_str(&html));
+    b.bytes = html.len() as u64;
+}
+
+fn bench_parse_deeply_nested_fragment(b: &mut Bencher) {
+    let html = nested(500);
+
+    b.iter(|| Fragment::parse_str(&html));
+    b.bytes = html.len() as u64;
+}
+
+fn bench_serialize_article(b: &mut Bencher) {
+    let document = Document::parse_str(ARTICLE);
+
+    b.iter(|| serializer::to_vec(document.clone()).unwrap());
+    b.bytes = ARTICLE.len() as u64;
+}
+
+benchmark_group!(benches,
+                 bench_parse_article,
+                 bench_parse_article_arena,
+                 bench_parse_large_table,
+                 bench_parse_large_table_arena,
+                 bench_parse_deeply_nested_fragment,
+                 bench_serialize_article);
+benchmark_main!(benches);
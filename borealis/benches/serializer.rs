@@ -0,0 +1,109 @@
+#[macro_use]
+extern crate bencher;
+#[macro_use(qualname, ns, atom)]
+extern crate string_cache;
+extern crate borealis;
+
+use std::io::Write;
+
+use bencher::Bencher;
+
+use borealis::serializer::{self, SerializeDocument, SerializeNode, DocumentSerializer,
+                           NodeSerializer, EmptyAttrs};
+
+struct Table {
+    rows: usize,
+    columns: usize,
+}
+
+impl SerializeDocument for Table {
+    fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+        let mut s = s.doctype("html").node();
+        let mut html = s.element(qualname!(html, "html"), EmptyAttrs::new());
+        let mut body = html.element(qualname!(html, "body"), EmptyAttrs::new());
+        let mut table = body.element(qualname!(html, "table"), EmptyAttrs::new());
+
+        for row in 0..self.rows {
+            let class = if row % 2 == 0 { "even" } else { "odd" };
+            let attrs = [(&qualname!("", "class"), class)];
+            let mut tr = table.element(qualname!(html, "tr"), attrs.iter());
+
+            for column in 0..self.columns {
+                let mut td = tr.element(qualname!(html, "td"), EmptyAttrs::new());
+                td.text(&format!("{}:{}", row, column));
+            }
+        }
+    }
+}
+
+struct Nested(usize);
+
+impl<'a> SerializeNode for &'a Nested {
+    fn serialize_node<W: Write>(self, s: &mut NodeSerializer<W>) {
+        if self.0 == 0 {
+            s.text("leaf");
+        } else {
+            let attrs = [(&qualname!("", "class"), "layout")];
+            let mut div = s.element(qualname!(html, "div"), attrs.iter());
+            (&Nested(self.0 - 1)).serialize_node(&mut div);
+        }
+    }
+}
+
+impl SerializeDocument for Nested {
+    fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+        let mut s = s.doctype("html").node();
+        let mut html = s.element(qualname!(html, "html"), EmptyAttrs::new());
+        let mut body = html.element(qualname!(html, "body"), EmptyAttrs::new());
+        (&self).serialize_node(&mut body);
+    }
+}
+
+struct Escaping(usize);
+
+impl SerializeDocument for Escaping {
+    fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+        let mut s = s.doctype("html").node();
+        let mut html = s.element(qualname!(html, "html"), EmptyAttrs::new());
+        let mut body = html.element(qualname!(html, "body"), EmptyAttrs::new());
+
+        for _ in 0..self.0 {
+            let attrs = [(&qualname!("", "title"), "\"quoted\" & <angled>")];
+            let mut p = body.element(qualname!(html, "p"), attrs.iter());
+            p.text("if (a < b && b > c) { return \"&amp;\"; } \u{a0}<script>&</script>");
+        }
+    }
+}
+
+fn bench_large_table(b: &mut Bencher) {
+    b.iter(|| {
+        serializer::to_vec(Table {
+            rows: 1000,
+            columns: 10,
+        })
+            .unwrap()
+    });
+
+    b.bytes = serializer::to_vec(Table {
+                  rows: 1000,
+                  columns: 10,
+              })
+                  .unwrap()
+                  .len() as u64;
+}
+
+fn bench_deeply_nested(b: &mut Bencher) {
+    b.iter(|| serializer::to_vec(Nested(500)).unwrap());
+    b.bytes = serializer::to_vec(Nested(500)).unwrap().len() as u64;
+}
+
+fn bench_heavy_escaping(b: &mut Bencher) {
+    b.iter(|| serializer::to_vec(Escaping(1000)).unwrap());
+    b.bytes = serializer::to_vec(Escaping(1000)).unwrap().len() as u64;
+}
+
+benchmark_group!(benches,
+                 bench_large_table,
+                 bench_deeply_nested,
+                 bench_heavy_escaping);
+benchmark_main!(benches);
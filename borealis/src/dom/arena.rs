@@ -0,0 +1,451 @@
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::mem;
+use std::ops::Index;
+
+use html5ever::{Attribute, ParseOpts, parse_document, parse_fragment};
+use html5ever::tree_builder::{TreeSink, NodeOrText, QuirksMode};
+use html5ever::tendril::{StrTendril, TendrilSink};
+
+use string_cache::QualName;
+
+use super::{Handle, Node, ParseError};
+
+// Same as `Node`, but with owned strings so that the arena can be sent between threads.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeData {
+    CData(String),
+    Comment(String),
+    Doctype(String, String, String),
+    Document,
+    Element(QualName, Vec<(QualName, String)>),
+    ProcessingInstruction(String, String),
+    TemplateContents,
+    Text(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+#[derive(Clone, Debug)]
+pub struct ArenaNode {
+    data: NodeData,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    template_contents: Option<NodeId>,
+}
+
+impl ArenaNode {
+    pub fn data(&self) -> &NodeData {
+        &self.data
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    // The contents of a `<template>`, as with `Handle::template_contents` they aren't children.
+    pub fn template_contents(&self) -> Option<NodeId> {
+        self.template_contents
+    }
+}
+
+// A DOM where all nodes live in a single vector and refer to each other by index. Unlike the
+// `Handle` tree it is `Send + Sync`, so a parsed document can be cached and shared.
+#[derive(Clone, Debug)]
+pub struct Arena {
+    nodes: Vec<ArenaNode>,
+    errors: Vec<ParseError>,
+    quirks_mode: QuirksMode,
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        let mut arena = Arena {
+            nodes: Vec::new(),
+            errors: Vec::new(),
+            quirks_mode: QuirksMode::NoQuirks,
+        };
+
+        arena.push(NodeData::Document);
+        arena
+    }
+
+    pub fn parse_document_str(s: &str) -> Arena {
+        let parser = parse_document(Arena::new(), ParseOpts::default()).from_utf8();
+        parser.one(s.as_bytes())
+    }
+
+    pub fn parse_fragment_str(s: &str, context: QualName) -> Arena {
+        let parser = parse_fragment(Arena::new(), ParseOpts::default(), context, Vec::new())
+                         .from_utf8();
+        parser.one(s.as_bytes())
+    }
+
+    pub fn document(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn fragment(&self) -> Vec<NodeId> {
+        let html = self[self.document()]
+                       .children
+                       .iter()
+                       .find(|&&c| self.is_element(c))
+                       .cloned()
+                       .expect("expected html element in fragment");
+
+        self[html].children.clone()
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    // Copies a `Handle` tree into a new arena. Documents become the arena's document node,
+    // other nodes are appended to it.
+    pub fn from_handle(handle: &Handle) -> Arena {
+        let mut arena = Arena::new();
+        let document = arena.document();
+
+        match handle.borrow().0 {
+            Node::Document(ref children) => {
+                for child in children {
+                    arena.copy_handle(document, child);
+                }
+            }
+            _ => arena.copy_handle(document, handle),
+        }
+
+        arena
+    }
+
+    pub fn to_handle(&self, id: NodeId) -> Handle {
+        let node = match self[id].data {
+            NodeData::CData(ref text) => Node::CData(text[..].into()),
+            NodeData::Comment(ref text) => Node::Comment(text[..].into()),
+            NodeData::Doctype(ref name, ref public_id, ref system_id) => {
+                Node::Doctype(name[..].into(), public_id[..].into(), system_id[..].into())
+            }
+            NodeData::Document => Node::Document(Vec::new()),
+            NodeData::Element(ref name, ref attributes) => {
+                let attributes = attributes.iter()
+                                           .map(|a| (a.0.clone(), a.1[..].into()))
+                                           .collect();
+                Node::Element(name.clone(), attributes, Vec::new())
+            }
+            NodeData::ProcessingInstruction(ref target, ref data) => {
+                Node::ProcessingInstruction(target[..].into(), data[..].into())
+            }
+            NodeData::TemplateContents => Node::TemplateContents(Vec::new()),
+            NodeData::Text(ref text) => Node::Text(text[..].into()),
+        };
+
+        let handle: Handle = node.into();
+
+        for &child in self[id].children.iter() {
+            handle.append_child(self.to_handle(child));
+        }
+
+        if let (Some(contents), Some(contents_id)) = (handle.template_contents(),
+                                                      self[id].template_contents) {
+            for &child in self[contents_id].children.iter() {
+                contents.append_child(self.to_handle(child));
+            }
+        }
+
+        handle
+    }
+
+    fn copy_handle(&mut self, parent: NodeId, handle: &Handle) {
+        let (data, children) = match handle.borrow().0 {
+            Node::CData(ref text) => (NodeData::CData(text.to_string()), Vec::new()),
+            Node::Comment(ref text) => (NodeData::Comment(text.to_string()), Vec::new()),
+            Node::Doctype(ref name, ref public_id, ref system_id) => {
+                let doctype = NodeData::Doctype(name.to_string(),
+                                                public_id.to_string(),
+                                                system_id.to_string());
+                (doctype, Vec::new())
+            }
+            Node::Document(ref children) => (NodeData::Document, children.clone()),
+            Node::Element(ref name, ref attributes, ref children) => {
+                let attributes = attributes.iter()
+                                           .map(|a| (a.0.clone(), a.1.to_string()))
+                                           .collect();
+                (NodeData::Element(name.clone(), attributes), children.clone())
+            }
+            Node::ProcessingInstruction(ref target, ref data) => {
+                (NodeData::ProcessingInstruction(target.to_string(), data.to_string()),
+                 Vec::new())
+            }
+            Node::TemplateContents(ref children) => (NodeData::TemplateContents, children.clone()),
+            Node::Text(ref text) => (NodeData::Text(text.to_string()), Vec::new()),
+        };
+
+        let id = self.push(data);
+        self.append_node(parent, id);
+
+        for child in children.iter() {
+            self.copy_handle(id, child);
+        }
+
+        if let Some(contents) = handle.template_contents() {
+            let contents_id = self.push(NodeData::TemplateContents);
+            self.nodes[id.0].template_contents = Some(contents_id);
+
+            for child in contents.children() {
+                self.copy_handle(contents_id, &child);
+            }
+        }
+    }
+
+    fn push(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(ArenaNode {
+            data: data,
+            parent: None,
+            children: Vec::new(),
+            template_contents: None,
+        });
+
+        NodeId(self.nodes.len() - 1)
+    }
+
+    fn is_element(&self, id: NodeId) -> bool {
+        match self[id].data {
+            NodeData::Element(..) => true,
+            _ => false,
+        }
+    }
+
+    fn append_node(&mut self, parent: NodeId, child: NodeId) {
+        self.nodes[child.0].parent = Some(parent);
+        self.nodes[parent.0].children.push(child);
+    }
+
+    // Appends the text to `target` if it is a text node, like `Dom` does with adjacent text.
+    fn merge_text(&mut self, target: Option<NodeId>, text: &str) -> bool {
+        if let Some(target) = target {
+            if let NodeData::Text(ref mut target_text) = self.nodes[target.0].data {
+                target_text.push_str(text);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn node_or_text_as_id(&mut self, child: NodeOrText<NodeId>) -> NodeId {
+        match child {
+            NodeOrText::AppendText(text) => self.push(NodeData::Text(text.to_string())),
+            NodeOrText::AppendNode(node) => node,
+        }
+    }
+}
+
+impl Index<NodeId> for Arena {
+    type Output = ArenaNode;
+
+    fn index(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0]
+    }
+}
+
+impl TreeSink for Arena {
+    type Output = Self;
+    type Handle = NodeId;
+
+    fn finish(self) -> Arena {
+        self
+    }
+
+    fn parse_error(&mut self, message: Cow<'static, str>) {
+        self.errors.push(ParseError {
+            message: message,
+            position: None,
+        });
+    }
+
+    fn get_document(&mut self) -> NodeId {
+        self.document()
+    }
+
+    fn get_template_contents(&self, target: NodeId) -> NodeId {
+        self[target].template_contents.expect("expected template")
+    }
+
+    fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+    }
+
+    fn same_node(&self, a: NodeId, b: NodeId) -> bool {
+        a == b
+    }
+
+    fn elem_name(&self, target: NodeId) -> QualName {
+        match self[target].data {
+            NodeData::Element(ref name, _) => name.clone(),
+            _ => panic!("expected element, got: {:?}", self[target]),
+        }
+    }
+
+    fn create_element(&mut self, name: QualName, attributes: Vec<Attribute>) -> NodeId {
+        let is_template = name == qualname!(html, "template");
+        let attributes = attributes.into_iter().map(|a| (a.name, a.value.to_string())).collect();
+        let element = self.push(NodeData::Element(name, attributes));
+
+        if is_template {
+            let contents = self.push(NodeData::TemplateContents);
+            self.nodes[element.0].template_contents = Some(contents);
+        }
+
+        element
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> NodeId {
+        self.push(NodeData::Comment(text.to_string()))
+    }
+
+    fn append(&mut self, parent: NodeId, child: NodeOrText<NodeId>) {
+        if let NodeOrText::AppendText(ref text) = child {
+            let last = self[parent].children.last().cloned();
+
+            if self.merge_text(last, text) {
+                return;
+            }
+        }
+
+        let child = self.node_or_text_as_id(child);
+        self.append_node(parent, child);
+    }
+
+    fn append_before_sibling(&mut self,
+                             sibling: NodeId,
+                             child: NodeOrText<NodeId>)
+                             -> Result<(), NodeOrText<NodeId>> {
+        let parent = self[sibling].parent.expect("expected sibling with a parent");
+        let index = self[parent].children.iter().position(|&c| c == sibling).unwrap();
+
+        if let NodeOrText::AppendText(ref text) = child {
+            let previous = if index > 0 { Some(self[parent].children[index - 1]) } else { None };
+
+            if self.merge_text(previous, text) {
+                return Ok(());
+            }
+        }
+
+        let child = self.node_or_text_as_id(child);
+        self.nodes[child.0].parent = Some(parent);
+        self.nodes[parent.0].children.insert(index, child);
+
+        Ok(())
+    }
+
+    fn append_doctype_to_document(&mut self,
+                                  name: StrTendril,
+                                  public_id: StrTendril,
+                                  system_id: StrTendril) {
+        let doctype = self.push(NodeData::Doctype(name.to_string(),
+                                                  public_id.to_string(),
+                                                  system_id.to_string()));
+        let document = self.document();
+        self.append_node(document, doctype);
+    }
+
+    fn add_attrs_if_missing(&mut self, target: NodeId, attrs: Vec<Attribute>) {
+        match self.nodes[target.0].data {
+            NodeData::Element(_, ref mut old_attrs) => {
+                let names = old_attrs.iter().map(|a| a.0.clone()).collect::<HashSet<_>>();
+                let missing = attrs.into_iter().filter(|a| !names.contains(&a.name));
+
+                old_attrs.extend(missing.map(|a| (a.name, a.value.to_string())));
+            }
+            _ => panic!("expected element, got {:?}", self.nodes[target.0]),
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: NodeId) {
+        if let Some(parent) = self.nodes[target.0].parent.take() {
+            self.nodes[parent.0].children.retain(|&c| c != target);
+        }
+    }
+
+    fn reparent_children(&mut self, old_parent: NodeId, new_parent: NodeId) {
+        let children = mem::replace(&mut self.nodes[old_parent.0].children, Vec::new());
+
+        for child in children {
+            self.append_node(new_parent, child);
+        }
+    }
+
+    fn mark_script_already_started(&mut self, _: NodeId) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::{Document, Fragment};
+    use serializer;
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Arena>();
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let html = "<!DOCTYPE html><html><head><title>a</title></head>\
+                    <body><p class=\"b\">c<br>d</p><template><i>e</i></template></body></html>";
+        let arena = Arena::parse_document_str(html);
+
+        assert_eq!(serializer::to_string(arena.to_handle(arena.document())).unwrap(),
+                   serializer::to_string(Document::parse_str(html)).unwrap());
+        assert!(arena.to_handle(arena.document()).deep_eq(&Document::parse_str(html).handle()));
+
+        let template = arena.nodes.iter().find(|n| n.template_contents().is_some()).unwrap();
+        let contents = template.template_contents().unwrap();
+
+        assert!(template.children().is_empty());
+        assert_eq!(arena[contents].children().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        let arena = Arena::parse_fragment_str("<tr><td>a</td></tr><tr></tr>",
+                                              qualname!(html, "tbody"));
+        let rows = arena.fragment();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(arena[rows[0]].children().len(), 1);
+
+        match *arena[arena[rows[0]].children()[0]].data() {
+            NodeData::Element(ref name, _) => assert_eq!(*name, qualname!(html, "td")),
+            ref data => panic!("expected td, got: {:?}", data),
+        }
+    }
+
+    #[test]
+    fn test_from_handle() {
+        let html = "<!DOCTYPE html><p>a<!--b--></p>";
+        let document = Document::parse_str(html).handle();
+        let arena = Arena::from_handle(&document);
+
+        assert!(arena.to_handle(arena.document()).deep_eq(&document));
+
+        let p = Fragment::parse_str("<p>a</p>").handles().remove(0);
+        let arena = Arena::from_handle(&p);
+        let root = arena[arena.document()].children()[0];
+
+        assert_eq!(arena[root].parent(), Some(arena.document()));
+        assert!(arena.to_handle(root).deep_eq(&p));
+    }
+}
@@ -0,0 +1,148 @@
+
+use html5ever::tendril::StrTendril;
+
+use string_cache::QualName;
+
+use super::{Handle, Node};
+
+#[derive(Clone, Debug, Default)]
+pub struct CompareOpts {
+    // Skips whitespace-only text nodes and collapses runs of whitespace in the others.
+    pub ignore_whitespace: bool,
+    pub ignore_attribute_order: bool,
+}
+
+impl Handle {
+    pub fn deep_eq(&self, other: &Handle) -> bool {
+        self.deep_eq_with(other, &CompareOpts::default())
+    }
+
+    pub fn deep_eq_with(&self, other: &Handle, opts: &CompareOpts) -> bool {
+        node_eq(&self.borrow().0, &other.borrow().0, opts) &&
+        match (self.template_contents(), other.template_contents()) {
+            (Some(a), Some(b)) => a.deep_eq_with(&b, opts),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+// Nodes are equal when their trees are, like with `deep_eq`.
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        node_eq(self, other, &CompareOpts::default())
+    }
+}
+
+fn node_eq(a: &Node, b: &Node, opts: &CompareOpts) -> bool {
+    match (a, b) {
+        (&Node::CData(ref a), &Node::CData(ref b)) |
+        (&Node::Comment(ref a), &Node::Comment(ref b)) => a == b,
+        (&Node::Doctype(ref a_name, ref a_public_id, ref a_system_id),
+         &Node::Doctype(ref b_name, ref b_public_id, ref b_system_id)) => {
+            a_name == b_name && a_public_id == b_public_id && a_system_id == b_system_id
+        }
+        (&Node::Document(ref a), &Node::Document(ref b)) |
+        (&Node::TemplateContents(ref a), &Node::TemplateContents(ref b)) => {
+            children_eq(a, b, opts)
+        }
+        (&Node::Element(ref a_name, ref a_attributes, ref a_children),
+         &Node::Element(ref b_name, ref b_attributes, ref b_children)) => {
+            a_name == b_name && attributes_eq(a_attributes, b_attributes, opts) &&
+            children_eq(a_children, b_children, opts)
+        }
+        (&Node::ProcessingInstruction(ref a_target, ref a_data),
+         &Node::ProcessingInstruction(ref b_target, ref b_data)) => {
+            a_target == b_target && a_data == b_data
+        }
+        (&Node::Text(ref a), &Node::Text(ref b)) => {
+            if opts.ignore_whitespace {
+                a.split_whitespace().eq(b.split_whitespace())
+            } else {
+                a == b
+            }
+        }
+        _ => false,
+    }
+}
+
+fn children_eq(a: &[Handle], b: &[Handle], opts: &CompareOpts) -> bool {
+    let a = significant_children(a, opts);
+    let b = significant_children(b, opts);
+
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.deep_eq_with(b, opts))
+}
+
+fn significant_children<'a>(children: &'a [Handle], opts: &CompareOpts) -> Vec<&'a Handle> {
+    children.iter()
+            .filter(|c| {
+                !opts.ignore_whitespace ||
+                match c.borrow().0 {
+                    Node::Text(ref text) => !text.trim().is_empty(),
+                    _ => true,
+                }
+            })
+            .collect()
+}
+
+fn attributes_eq(a: &[(QualName, StrTendril)],
+                 b: &[(QualName, StrTendril)],
+                 opts: &CompareOpts)
+                 -> bool {
+    if opts.ignore_attribute_order {
+        a.len() == b.len() && a.iter().all(|a| b.contains(a))
+    } else {
+        a == b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::Fragment;
+
+    fn parse(s: &str) -> Handle {
+        Fragment::parse_str(s).handles().remove(0)
+    }
+
+    #[test]
+    fn test_deep_eq() {
+        let a = parse("<p class=\"a\" id=\"b\">a <br> b</p>");
+
+        assert!(a.deep_eq(&parse("<p class=\"a\" id=\"b\">a <br> b</p>")));
+        assert!(!a.deep_eq(&parse("<p class=\"a\" id=\"b\">a <br> c</p>")));
+        assert!(!a.deep_eq(&parse("<p class=\"a\">a <br> b</p>")));
+        assert!(!a.deep_eq(&parse("<div class=\"a\" id=\"b\">a <br> b</div>")));
+        assert!(a != parse("<p class=\"a\" id=\"b\">a <br> b</p>"));
+        assert!(a.borrow().0 == parse("<p class=\"a\" id=\"b\">a <br> b</p>").borrow().0);
+        assert!(a.borrow().0 != parse("<p class=\"a\" id=\"b\">a <br> c</p>").borrow().0);
+
+        let template = parse("<template>a</template>");
+
+        assert!(template.deep_eq(&parse("<template>a</template>")));
+        assert!(!template.deep_eq(&parse("<template>b</template>")));
+    }
+
+    #[test]
+    fn test_documents_and_fragments_eq() {
+        use dom::Document;
+
+        assert_eq!(Document::parse_str("<p>a</p>"), Document::parse_str("<p>a</p>"));
+        assert!(Document::parse_str("<p>a</p>") != Document::parse_str("<p>b</p>"));
+        assert_eq!(Fragment::parse_str("<p>a</p>b"), Fragment::parse_str("<p>a</p>b"));
+        assert!(Fragment::parse_str("<p>a</p>b") != Fragment::parse_str("<p>a</p>"));
+    }
+
+    #[test]
+    fn test_deep_eq_with() {
+        let opts = CompareOpts {
+            ignore_whitespace: true,
+            ignore_attribute_order: true,
+        };
+        let a = parse("<ul id=\"a\" class=\"b\">\n  <li>a  b</li>\n</ul>");
+        let b = parse("<ul class=\"b\" id=\"a\"><li> a b </li></ul>");
+
+        assert!(!a.deep_eq(&b));
+        assert!(a.deep_eq_with(&b, &opts));
+        assert!(!a.deep_eq_with(&parse("<ul class=\"b\" id=\"a\"><li>ab</li></ul>"), &opts));
+    }
+}
@@ -0,0 +1,208 @@
+
+use std::ascii::AsciiExt;
+use std::borrow::Cow;
+use std::cmp;
+use std::io::{ErrorKind, Read, Result};
+use std::str;
+
+use enc::{EncodingRef, RawDecoder};
+use enc::all::{UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+use enc::label::encoding_from_whatwg_label;
+
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tendril::fmt::UTF8;
+
+// Number of bytes that are searched for a `<meta charset>`.
+const PRESCAN_LENGTH: u64 = 1024;
+const BUFFER_LENGTH: usize = 8192;
+
+// Decodes everything read from `reader` and feeds it to `sink` as it goes.
+//
+// The encoding is picked like a browser would pick it, a byte order mark takes precedence
+// over `encoding`, which in turn takes precedence over a `<meta>` tag in the first 1024 bytes.
+// If none of them are available `fallback` is used.
+pub fn decode<R, S>(mut reader: R,
+                    encoding: Option<EncodingRef>,
+                    fallback: EncodingRef,
+                    mut sink: S)
+                    -> Result<S::Output>
+    where R: Read,
+          S: TendrilSink<UTF8>
+{
+    let mut head = Vec::new();
+    try!((&mut reader).take(PRESCAN_LENGTH).read_to_end(&mut head));
+
+    let (encoding, bom_length) = match sniff_bom(&head) {
+        Some(bom) => bom,
+        None => (encoding.or_else(|| prescan(&head)).unwrap_or(fallback), 0),
+    };
+
+    let mut decoder = encoding.raw_decoder();
+    let mut pending = Vec::new();
+    decode_chunk(&mut *decoder, &mut pending, &head[bom_length..], &mut sink);
+
+    let mut buffer = [0; BUFFER_LENGTH];
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => decode_chunk(&mut *decoder, &mut pending, &buffer[..n], &mut sink),
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    let mut output = String::new();
+    if decoder.raw_finish(&mut output).is_some() {
+        output.push('\u{fffd}');
+    }
+
+    if !output.is_empty() {
+        sink.process(StrTendril::from(output));
+    }
+
+    Ok(sink.finish())
+}
+
+// `pending` holds the bytes of earlier chunks that the decoder hasn't finished with. An error
+// can point back into them, with a negative offset, when a sequence starts in an earlier chunk.
+fn decode_chunk<S>(decoder: &mut RawDecoder, pending: &mut Vec<u8>, chunk: &[u8], sink: &mut S)
+    where S: TendrilSink<UTF8>
+{
+    let mut output = String::new();
+    let mut input = Cow::Borrowed(chunk);
+
+    loop {
+        let (processed, err) = decoder.raw_feed(&input, &mut output);
+
+        match err {
+            Some(err) if err.upto < 0 => {
+                output.push('\u{fffd}');
+
+                let start = cmp::max(pending.len() as isize + err.upto, 0) as usize;
+                let mut rest = pending[start..].to_vec();
+                rest.extend_from_slice(&input);
+
+                pending.clear();
+                input = Cow::Owned(rest);
+            }
+            Some(err) => {
+                output.push('\u{fffd}');
+
+                let rest = input[err.upto as usize..].to_vec();
+                pending.clear();
+                input = Cow::Owned(rest);
+            }
+            None => {
+                if processed > 0 {
+                    pending.clear();
+                }
+
+                pending.extend_from_slice(&input[processed..]);
+                break;
+            }
+        }
+    }
+
+    if !output.is_empty() {
+        sink.process(StrTendril::from(output));
+    }
+}
+
+fn sniff_bom(bytes: &[u8]) -> Option<(EncodingRef, usize)> {
+    if bytes.starts_with(b"\xef\xbb\xbf") {
+        Some((UTF_8 as EncodingRef, 3))
+    } else if bytes.starts_with(b"\xfe\xff") {
+        Some((UTF_16BE as EncodingRef, 2))
+    } else if bytes.starts_with(b"\xff\xfe") {
+        Some((UTF_16LE as EncodingRef, 2))
+    } else {
+        None
+    }
+}
+
+// A simplified version of the prescan algorithm from the HTML specification.
+fn prescan(bytes: &[u8]) -> Option<EncodingRef> {
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+
+        if rest.starts_with(b"<!--") {
+            i = find(bytes, i + 4, b"-->").map_or(bytes.len(), |end| end + 3);
+        } else if rest.len() > 5 && rest[..5].eq_ignore_ascii_case(b"<meta") &&
+                  (is_space(rest[5]) || rest[5] == b'/') {
+            let end = find(bytes, i, b">").unwrap_or(bytes.len());
+
+            if let Some(encoding) = meta_charset(&bytes[i + 5..end]) {
+                return Some(encoding);
+            }
+
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+
+    None
+}
+
+// Finds the `charset` in either `<meta charset="..">` or
+// `<meta http-equiv="content-type" content="text/html; charset=..">`.
+fn meta_charset(attributes: &[u8]) -> Option<EncodingRef> {
+    let attributes = attributes.to_ascii_lowercase();
+
+    let mut i = match find(&attributes, 0, b"charset") {
+        Some(i) => i + 7,
+        None => return None,
+    };
+
+    while i < attributes.len() && is_space(attributes[i]) {
+        i += 1;
+    }
+
+    if attributes.get(i) != Some(&b'=') {
+        return None;
+    }
+
+    i += 1;
+
+    while i < attributes.len() && (is_space(attributes[i]) || attributes[i] == b'"' ||
+                                   attributes[i] == b'\'') {
+        i += 1;
+    }
+
+    let end = attributes[i..]
+                  .iter()
+                  .position(|&b| is_space(b) || b == b'"' || b == b'\'' || b == b';')
+                  .map_or(attributes.len(), |end| i + end);
+
+    let label = match str::from_utf8(&attributes[i..end]) {
+        Ok(label) => label,
+        Err(_) => return None,
+    };
+
+    encoding_from_whatwg_label(label).map(|encoding| {
+        // A document that could be read as ASCII can't be UTF-16.
+        match encoding.whatwg_name() {
+            Some("utf-16be") | Some("utf-16le") => UTF_8 as EncodingRef,
+            Some("x-user-defined") => WINDOWS_1252 as EncodingRef,
+            _ => encoding,
+        }
+    })
+}
+
+fn find(bytes: &[u8], start: usize, needle: &[u8]) -> Option<usize> {
+    if start > bytes.len() {
+        return None;
+    }
+
+    bytes[start..].windows(needle.len()).position(|w| w == needle).map(|i| start + i)
+}
+
+fn is_space(b: u8) -> bool {
+    match b {
+        b' ' | b'\t' | b'\n' | b'\x0c' | b'\r' => true,
+        _ => false,
+    }
+}
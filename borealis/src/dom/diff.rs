@@ -0,0 +1,360 @@
+
+use std::cmp;
+use std::fmt;
+
+use html5ever::tendril::StrTendril;
+
+use string_cache::QualName;
+
+use super::{Handle, Node};
+
+// Template contents aren't children, they get their own step in paths.
+const CONTENTS: &'static str = "#contents";
+
+/// The child indices from the root to a node, along with the names of the nodes on the way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodePath(Vec<(usize, String)>);
+
+impl NodePath {
+    /// Returns the child indices, without the names.
+    pub fn indices(&self) -> Vec<usize> {
+        self.0.iter().map(|c| c.0).collect()
+    }
+
+    /// Follows the path from `root`, returning `None` if a step doesn't exist.
+    pub fn resolve(&self, root: &Handle) -> Option<Handle> {
+        self.0.iter().fold(Some(root.clone()), |node, c| {
+            node.and_then(|n| {
+                if c.1 == CONTENTS {
+                    n.template_contents()
+                } else {
+                    n.children().nth(c.0)
+                }
+            })
+        })
+    }
+
+    fn child(&self, index: usize, node: &Handle) -> NodePath {
+        let mut path = self.0.clone();
+        path.push((index, name(node)));
+        NodePath(path)
+    }
+
+    fn contents(&self) -> NodePath {
+        let mut path = self.0.clone();
+        path.push((0, CONTENTS.to_owned()));
+        NodePath(path)
+    }
+}
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+
+        for &(index, ref name) in self.0.iter() {
+            try!(write!(f, "/{}[{}]", name, index));
+        }
+
+        Ok(())
+    }
+}
+
+/// Paths point into the first tree, except for inserted nodes whose last index is in the second.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Inserted(NodePath, Handle),
+    Removed(NodePath, Handle),
+    /// The nodes are of different kinds or names, so their children weren't compared.
+    Changed(NodePath, Handle, Handle),
+    AttributeAdded(NodePath, QualName, StrTendril),
+    AttributeRemoved(NodePath, QualName, StrTendril),
+    AttributeChanged(NodePath, QualName, StrTendril, StrTendril),
+    /// The text of a text, comment or cdata node changed.
+    TextChanged(NodePath, StrTendril, StrTendril),
+}
+
+impl Edit {
+    /// Returns the path of the node the edit applies to.
+    pub fn path(&self) -> &NodePath {
+        match *self {
+            Edit::Inserted(ref path, _) |
+            Edit::Removed(ref path, _) |
+            Edit::Changed(ref path, _, _) |
+            Edit::AttributeAdded(ref path, _, _) |
+            Edit::AttributeRemoved(ref path, _, _) |
+            Edit::AttributeChanged(ref path, _, _, _) |
+            Edit::TextChanged(ref path, _, _) => path,
+        }
+    }
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}: ", self.path()));
+
+        match *self {
+            Edit::Inserted(_, ref node) => write!(f, "inserted {}", describe(node)),
+            Edit::Removed(_, ref node) => write!(f, "removed {}", describe(node)),
+            Edit::Changed(_, ref a, ref b) => {
+                write!(f, "changed {} to {}", describe(a), describe(b))
+            }
+            Edit::AttributeAdded(_, ref name, ref value) => {
+                write!(f, "added attribute {}={:?}", name.local, &value[..])
+            }
+            Edit::AttributeRemoved(_, ref name, ref value) => {
+                write!(f, "removed attribute {}={:?}", name.local, &value[..])
+            }
+            Edit::AttributeChanged(_, ref name, ref a, ref b) => {
+                write!(f,
+                       "changed attribute {} from {:?} to {:?}",
+                       name.local,
+                       &a[..],
+                       &b[..])
+            }
+            Edit::TextChanged(_, ref a, ref b) => {
+                write!(f, "changed text from {:?} to {:?}", &a[..], &b[..])
+            }
+        }
+    }
+}
+
+/// The edits between two trees, in document order.
+#[derive(Clone, Debug)]
+pub struct Diff(Vec<Edit>);
+
+impl Diff {
+    /// Returns the edits.
+    pub fn edits(&self) -> &[Edit] {
+        &self.0
+    }
+
+    /// Returns true if the trees are the same.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// The report lists one edit per line, it is meant to be printed when a test fails.
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, edit) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(writeln!(f, ""));
+            }
+
+            try!(write!(f, "{}", edit));
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares two trees and lists the edits that turn `a` into `b`. Children are matched up by kind
+/// and name, so a single inserted element doesn't make all of its following siblings differ.
+pub fn diff(a: &Handle, b: &Handle) -> Diff {
+    let mut edits = Vec::new();
+    diff_nodes(a, b, &NodePath(Vec::new()), &mut edits);
+    Diff(edits)
+}
+
+fn diff_nodes(a: &Handle, b: &Handle, path: &NodePath, edits: &mut Vec<Edit>) {
+    let (a_node, b_node) = (a.borrow(), b.borrow());
+
+    match (&a_node.0, &b_node.0) {
+        (&Node::CData(ref a_text), &Node::CData(ref b_text)) |
+        (&Node::Comment(ref a_text), &Node::Comment(ref b_text)) |
+        (&Node::Text(ref a_text), &Node::Text(ref b_text)) => {
+            if a_text != b_text {
+                edits.push(Edit::TextChanged(path.clone(), a_text.clone(), b_text.clone()));
+            }
+        }
+        (&Node::Document(ref a_children), &Node::Document(ref b_children)) |
+        (&Node::TemplateContents(ref a_children), &Node::TemplateContents(ref b_children)) => {
+            diff_children(a_children, b_children, path, edits);
+        }
+        (&Node::Element(ref a_name, ref a_attributes, ref a_children),
+         &Node::Element(ref b_name, ref b_attributes, ref b_children)) if a_name == b_name => {
+            diff_attributes(a_attributes, b_attributes, path, edits);
+            diff_children(a_children, b_children, path, edits);
+
+            if let (Some(a), Some(b)) = (a.template_contents(), b.template_contents()) {
+                diff_nodes(&a, &b, &path.contents(), edits);
+            }
+        }
+        _ => {
+            if !a.deep_eq(b) {
+                edits.push(Edit::Changed(path.clone(), a.clone(), b.clone()));
+            }
+        }
+    }
+}
+
+fn diff_attributes(a: &[(QualName, StrTendril)],
+                   b: &[(QualName, StrTendril)],
+                   path: &NodePath,
+                   edits: &mut Vec<Edit>) {
+    for &(ref name, ref a_value) in a.iter() {
+        match b.iter().find(|b| b.0 == *name) {
+            Some(&(_, ref b_value)) if a_value != b_value => {
+                edits.push(Edit::AttributeChanged(path.clone(),
+                                                  name.clone(),
+                                                  a_value.clone(),
+                                                  b_value.clone()));
+            }
+            Some(_) => {}
+            None => edits.push(Edit::AttributeRemoved(path.clone(), name.clone(), a_value.clone())),
+        }
+    }
+
+    for &(ref name, ref b_value) in b.iter() {
+        if !a.iter().any(|a| a.0 == *name) {
+            edits.push(Edit::AttributeAdded(path.clone(), name.clone(), b_value.clone()));
+        }
+    }
+}
+
+// Matches up the children with the longest common subsequence of nodes of the same kind.
+fn diff_children(a: &[Handle], b: &[Handle], path: &NodePath, edits: &mut Vec<Edit>) {
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if same_kind(&a[i], &b[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lengths[i + 1][j], lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && same_kind(&a[i], &b[j]) &&
+           lengths[i][j] == lengths[i + 1][j + 1] + 1 {
+            diff_nodes(&a[i], &b[j], &path.child(i, &a[i]), edits);
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            edits.push(Edit::Removed(path.child(i, &a[i]), a[i].clone()));
+            i += 1;
+        } else {
+            edits.push(Edit::Inserted(path.child(j, &b[j]), b[j].clone()));
+            j += 1;
+        }
+    }
+}
+
+fn same_kind(a: &Handle, b: &Handle) -> bool {
+    match (&a.borrow().0, &b.borrow().0) {
+        (&Node::Element(ref a, _, _), &Node::Element(ref b, _, _)) => a == b,
+        (&Node::CData(_), &Node::CData(_)) |
+        (&Node::Comment(_), &Node::Comment(_)) |
+        (&Node::Doctype(..), &Node::Doctype(..)) |
+        (&Node::Document(_), &Node::Document(_)) |
+        (&Node::ProcessingInstruction(..), &Node::ProcessingInstruction(..)) |
+        (&Node::TemplateContents(_), &Node::TemplateContents(_)) |
+        (&Node::Text(_), &Node::Text(_)) => true,
+        _ => false,
+    }
+}
+
+fn name(handle: &Handle) -> String {
+    match handle.borrow().0 {
+        Node::CData(_) => "#cdata-section".to_owned(),
+        Node::Comment(_) => "#comment".to_owned(),
+        Node::Doctype(..) => "#doctype".to_owned(),
+        Node::Document(_) => "#document".to_owned(),
+        Node::Element(ref name, _, _) => name.local.to_string(),
+        Node::ProcessingInstruction(ref target, _) => format!("?{}", target),
+        Node::TemplateContents(_) => "#document-fragment".to_owned(),
+        Node::Text(_) => "#text".to_owned(),
+    }
+}
+
+fn describe(handle: &Handle) -> String {
+    match handle.borrow().0 {
+        Node::CData(ref text) => format!("<![CDATA[{}]]>", text),
+        Node::Comment(ref text) => format!("<!--{}-->", text),
+        Node::Doctype(ref name, _, _) => format!("<!DOCTYPE {}>", name),
+        Node::Element(ref name, ref attributes, _) => {
+            let mut s = format!("<{}", name.local);
+
+            for &(ref name, ref value) in attributes.iter() {
+                s.push_str(&format!(" {}={:?}", name.local, &value[..]));
+            }
+
+            s.push('>');
+            s
+        }
+        Node::ProcessingInstruction(ref target, ref data) => format!("<?{} {}>", target, data),
+        Node::Text(ref text) => format!("{:?}", &text[..]),
+        _ => name(handle),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::{Document, Fragment, Handle};
+
+    fn parse(s: &str) -> Handle {
+        Fragment::parse_str(s).handles().remove(0)
+    }
+
+    fn report(a: &str, b: &str) -> String {
+        diff(&parse(a), &parse(b)).to_string()
+    }
+
+    #[test]
+    fn test_equal() {
+        let a = Document::parse_str("<!DOCTYPE html><p class=\"a\">b<!--c--></p>").handle();
+        let b = Document::parse_str("<!DOCTYPE html><p class=\"a\">b<!--c--></p>").handle();
+
+        assert!(diff(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_text_and_attributes() {
+        assert_eq!(report("<p id=\"a\" class=\"b\">c<!--d--></p>",
+                          "<p class=\"x\" title=\"y\">z<!--d--></p>"),
+                   "/: removed attribute id=\"a\"\n\
+                    /: changed attribute class from \"b\" to \"x\"\n\
+                    /: added attribute title=\"y\"\n\
+                    /#text[0]: changed text from \"c\" to \"z\"");
+    }
+
+    #[test]
+    fn test_inserted_and_removed() {
+        let a = parse("<ul><li>a</li><li>b</li><br></ul>");
+        let b = parse("<ul><p>x</p><li>a</li><li>c</li></ul>");
+        let diff = diff(&a, &b);
+
+        assert_eq!(diff.to_string(),
+                   "/p[0]: inserted <p>\n\
+                    /li[1]/#text[0]: changed text from \"b\" to \"c\"\n\
+                    /br[2]: removed <br>");
+        assert_eq!(diff.edits()[1].path().indices(), &[1, 0]);
+        assert_eq!(diff.edits()[2].path().resolve(&a), a.children().nth(2));
+    }
+
+    #[test]
+    fn test_changed() {
+        assert_eq!(report("<div><p>a</p></div>", "<div><span>a</span></div>"),
+                   "/p[0]: removed <p>\n/span[0]: inserted <span>");
+        assert_eq!(report("<p>a</p>", "<div>a</div>"), "/: changed <p> to <div>");
+    }
+
+    #[test]
+    fn test_template_contents() {
+        let a = parse("<template><p>a</p></template>");
+        let diff = diff(&a, &parse("<template><p>b</p></template>"));
+
+        assert_eq!(diff.to_string(),
+                   "/#contents[0]/p[0]/#text[0]: changed text from \"a\" to \"b\"");
+        assert_eq!(diff.edits()[0].path().resolve(&a),
+                   a.template_contents().unwrap().children().next().unwrap().children().next());
+    }
+}
@@ -1,14 +1,19 @@
 
-use std::io::Write;
+use std::io::{self, Read, Write};
+
+use enc::EncodingRef;
+use enc::all::WINDOWS_1252;
 
 use html5ever::{ParseOpts, parse_document};
 use html5ever::tree_builder::TreeSink;
 use html5ever::tendril::TendrilSink;
 
 use serializer::{SerializeDocument, DocumentSerializer};
-use super::{Dom, Handle};
+use super::{Dom, Handle, Parsed, ParseError, Select, SelectorError};
+use super::decode::decode;
+use super::parse::{exact_errors_opts, feed_with_positions};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Document {
     node: Handle,
 }
@@ -20,13 +25,84 @@ impl Document {
         Document { node: dom.document() }
     }
 
+    pub fn parse_bytes(bytes: &[u8], encoding: Option<EncodingRef>) -> Document {
+        Document::parse_reader(bytes, encoding).unwrap()
+    }
+
+    pub fn parse_reader<R: Read>(reader: R,
+                                 encoding: Option<EncodingRef>)
+                                 -> io::Result<Document> {
+        let parser = parse_document(Dom::new(), ParseOpts::default());
+        let dom = try!(decode(reader, encoding, WINDOWS_1252, parser));
+        Ok(Document { node: dom.document() })
+    }
+
+    pub fn parse_str_with_errors(s: &str) -> Parsed<Document> {
+        let opts = exact_errors_opts();
+        let dom = Dom::new();
+        let position = dom.position.clone();
+        let dom = feed_with_positions(parse_document(dom, opts), &position, s);
+
+        Parsed {
+            output: Document { node: dom.document() },
+            errors: dom.errors,
+            quirks_mode: dom.quirks_mode,
+        }
+    }
+
+    pub fn parse_str_strict(s: &str) -> Result<Document, Vec<ParseError>> {
+        Document::parse_str_with_errors(s).into_result()
+    }
+
+    pub fn select(&self, selectors: &str) -> Result<Select, SelectorError> {
+        self.node.select(selectors)
+    }
+
+    pub fn select_first(&self, selectors: &str) -> Result<Option<Handle>, SelectorError> {
+        self.node.select_first(selectors)
+    }
+
+    pub fn rewrite_urls<F: Fn(&str) -> String>(&self, rewrite: F) {
+        self.node.rewrite_urls(rewrite);
+    }
+
     pub fn handle(self) -> Handle {
         self.node
     }
 }
 
+// Documents are equal when their trees are, see `Handle::deep_eq`.
+impl PartialEq for Document {
+    fn eq(&self, other: &Document) -> bool {
+        self.node.deep_eq(&other.node)
+    }
+}
+
 impl SerializeDocument for Document {
     fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
         self.node.serialize_document(s);
     }
 }
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Document {
+    fn serialize<S: ::serde::Serializer>(&self, s: &mut S) -> Result<(), S::Error> {
+        ::serde::Serialize::serialize(&self.node, s)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Deserialize for Document {
+    fn deserialize<D: ::serde::Deserializer>(d: &mut D) -> Result<Document, D::Error> {
+        use serde::de::Error;
+
+        let node: Handle = try!(::serde::Deserialize::deserialize(d));
+
+        match node.borrow().0 {
+            super::Node::Document(_) => {}
+            _ => return Err(D::Error::invalid_value("expected a document")),
+        }
+
+        Ok(Document { node: node })
+    }
+}
@@ -1,34 +1,113 @@
 
-use std::io::Write;
+use std::io::{self, Read, Write};
+
+use enc::EncodingRef;
+use enc::all::UTF_8;
 
 use html5ever::{ParseOpts, parse_fragment};
 use html5ever::tree_builder::TreeSink;
 use html5ever::tendril::TendrilSink;
 
+use string_cache::QualName;
+
 use serializer::{SerializeNode, NodeSerializer};
-use super::{Dom, Handle};
+use super::{Dom, Handle, Parsed, ParseError, Select, SelectorError};
+use super::decode::decode;
+use super::parse::{exact_errors_opts, feed_with_positions};
+use super::sanitize::{self, SanitizePolicy};
+use super::select;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Fragment {
     nodes: Vec<Handle>,
 }
 
 impl Fragment {
     pub fn parse_str(s: &str) -> Fragment {
-        let parser = parse_fragment(Dom::new(),
-                                    ParseOpts::default(),
-                                    qualname!(html, "body"),
-                                    Vec::new())
+        Fragment::parse_str_in_context(s, qualname!(html, "body"))
+    }
+
+    pub fn parse_str_in_context(s: &str, context: QualName) -> Fragment {
+        let parser = parse_fragment(Dom::new(), ParseOpts::default(), context, Vec::new())
                          .from_utf8();
         let dom = parser.one(s.as_bytes());
         Fragment { nodes: dom.fragment() }
     }
 
+    pub fn parse_bytes(bytes: &[u8], encoding: Option<EncodingRef>) -> Fragment {
+        Fragment::parse_reader(bytes, encoding).unwrap()
+    }
+
+    pub fn parse_reader<R: Read>(reader: R,
+                                 encoding: Option<EncodingRef>)
+                                 -> io::Result<Fragment> {
+        Fragment::parse_reader_in_context(reader, encoding, qualname!(html, "body"))
+    }
+
+    // Unlike documents, fragments without a byte order mark or `<meta>` are read as UTF-8.
+    pub fn parse_reader_in_context<R: Read>(reader: R,
+                                            encoding: Option<EncodingRef>,
+                                            context: QualName)
+                                            -> io::Result<Fragment> {
+        let parser = parse_fragment(Dom::new(), ParseOpts::default(), context, Vec::new());
+        let dom = try!(decode(reader, encoding, UTF_8, parser));
+        Ok(Fragment { nodes: dom.fragment() })
+    }
+
+    pub fn parse_str_with_errors(s: &str) -> Parsed<Fragment> {
+        Fragment::parse_str_with_errors_in_context(s, qualname!(html, "body"))
+    }
+
+    pub fn parse_str_with_errors_in_context(s: &str, context: QualName) -> Parsed<Fragment> {
+        let opts = exact_errors_opts();
+        let dom = Dom::new();
+        let position = dom.position.clone();
+        let parser = parse_fragment(dom, opts, context, Vec::new());
+        let dom = feed_with_positions(parser, &position, s);
+
+        Parsed {
+            output: Fragment { nodes: dom.fragment() },
+            errors: dom.errors,
+            quirks_mode: dom.quirks_mode,
+        }
+    }
+
+    pub fn parse_str_strict(s: &str) -> Result<Fragment, Vec<ParseError>> {
+        Fragment::parse_str_with_errors(s).into_result()
+    }
+
+    pub fn select(&self, selectors: &str) -> Result<Select, SelectorError> {
+        select::select(&self.nodes, selectors)
+    }
+
+    pub fn select_first(&self, selectors: &str) -> Result<Option<Handle>, SelectorError> {
+        self.select(selectors).map(|mut s| s.next())
+    }
+
+    pub fn rewrite_urls<F: Fn(&str) -> String>(&self, rewrite: F) {
+        for node in self.nodes.iter() {
+            node.rewrite_urls(&rewrite);
+        }
+    }
+
+    /// Returns a sanitized copy of the fragment, to be used for untrusted input.
+    pub fn sanitize(&self, policy: &SanitizePolicy) -> Fragment {
+        Fragment { nodes: sanitize::sanitize(&self.nodes, policy) }
+    }
+
     pub fn handles(self) -> Vec<Handle> {
         self.nodes
     }
 }
 
+// Fragments are equal when their trees are, see `Handle::deep_eq`.
+impl PartialEq for Fragment {
+    fn eq(&self, other: &Fragment) -> bool {
+        self.nodes.len() == other.nodes.len() &&
+        self.nodes.iter().zip(other.nodes.iter()).all(|(a, b)| a.deep_eq(b))
+    }
+}
+
 impl SerializeNode for Fragment {
     fn serialize_node<W: Write>(self, s: &mut NodeSerializer<W>) {
         for child in self.nodes.iter() {
@@ -36,3 +115,17 @@ impl SerializeNode for Fragment {
         }
     }
 }
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Fragment {
+    fn serialize<S: ::serde::Serializer>(&self, s: &mut S) -> Result<(), S::Error> {
+        ::serde::Serialize::serialize(&self.nodes, s)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Deserialize for Fragment {
+    fn deserialize<D: ::serde::Deserializer>(d: &mut D) -> Result<Fragment, D::Error> {
+        Ok(Fragment { nodes: try!(::serde::Deserialize::deserialize(d)) })
+    }
+}
@@ -10,55 +10,118 @@ use html5ever::tendril::{StrTendril, TendrilSink};
 use string_cache::QualName;
 
 use serializer::{SerializeDocument, SerializeNode, DocumentSerializer, NodeSerializer};
+use super::Position;
 
-#[derive(Clone, Debug, PartialEq)]
+// Compared like `Handle::deep_eq`, see compare.rs.
+#[derive(Clone, Debug)]
 pub enum Node {
+    // The parser doesn't produce CDATA sections, they come from `Dom::create_cdata` or are
+    // built by hand.
+    CData(StrTendril),
     Comment(StrTendril),
-    Document(Option<StrTendril>, Option<Handle>),
+    Doctype(StrTendril, StrTendril, StrTendril),
+    Document(Vec<Handle>),
     Element(QualName, Vec<(QualName, StrTendril)>, Vec<Handle>),
+    // The parser reads processing instructions as comments, these come from `Dom::create_pi`
+    // or are built by hand.
+    ProcessingInstruction(StrTendril, StrTendril),
+    TemplateContents(Vec<Handle>),
     Text(StrTendril),
 }
 
+#[derive(Debug)]
+struct Inner {
+    node: RefCell<(Node, Option<WeakHandle>)>,
+    position: Option<Position>,
+    // The inert contents of a `<template>`, they aren't among its children.
+    template_contents: Option<Handle>,
+}
+
 #[derive(Clone, Debug)]
-pub struct Handle(Rc<RefCell<(Node, Option<WeakHandle>)>>);
+pub struct Handle(Rc<Inner>);
 
 impl Handle {
+    /// Where the node starts in the source.
+    ///
+    /// Only `Document::parse_str_with_errors` and `Fragment::parse_str_with_errors` keep track
+    /// of positions. This is always `None` for nodes parsed with `parse_str`, `parse_bytes` or
+    /// `parse_reader`, and for nodes built by hand.
+    pub fn position(&self) -> Option<Position> {
+        self.0.position
+    }
+
     pub fn downgrade(&self) -> WeakHandle {
         WeakHandle(Rc::downgrade(&self.0))
     }
+
+    pub fn parent(&self) -> Option<Handle> {
+        match self.borrow().1 {
+            Some(ref parent) => parent.0.upgrade().map(Handle),
+            None => None,
+        }
+    }
+
+    // The document fragment holding the contents of a `<template>` element. It isn't one of
+    // the element's children, so iterating, selecting and mutating children leave it alone.
+    pub fn template_contents(&self) -> Option<Handle> {
+        self.0.template_contents.clone()
+    }
 }
 
 impl From<Node> for Handle {
     fn from(node: Node) -> Handle {
-        Handle(Rc::new(RefCell::new((node, None))))
+        new_handle(node, None)
     }
 }
 
 impl Deref for Handle {
-    type Target = Rc<RefCell<(Node, Option<WeakHandle>)>>;
+    type Target = RefCell<(Node, Option<WeakHandle>)>;
 
-    fn deref(&self) -> &Rc<RefCell<(Node, Option<WeakHandle>)>> {
-        &self.0
+    fn deref(&self) -> &RefCell<(Node, Option<WeakHandle>)> {
+        &self.0.node
     }
 }
 
+// Handles are equal when they point to the same node, trees are compared with `deep_eq`.
 impl PartialEq for Handle {
     fn eq(&self, other: &Handle) -> bool {
-        self.borrow().0 == other.borrow().0
+        Rc::ptr_eq(&self.0, &other.0)
     }
 }
 
+impl Eq for Handle {}
+
 impl SerializeDocument for Handle {
     fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
         match *self.borrow() {
-            (Node::Document(ref doctype, ref node), _) => {
-                let mut s = match *doctype {
-                    Some(ref name) => s.doctype(&name).node(),
+            (Node::Document(ref children), _) => {
+                let mut s = s;
+                let mut children = &children[..];
+
+                while let Some((child, rest)) = children.split_first() {
+                    match *child.borrow() {
+                        (Node::Comment(ref comment), _) => s = s.comment(&comment),
+                        _ => break,
+                    }
+
+                    children = rest;
+                }
+
+                let mut s = match children.split_first() {
+                    Some((child, rest)) => {
+                        match *child.borrow() {
+                            (Node::Doctype(ref name, ref public_id, ref system_id), _) => {
+                                children = rest;
+                                s.doctype_with_ids(&name, &public_id, &system_id).node()
+                            }
+                            _ => s.node(),
+                        }
+                    }
                     None => s.node(),
                 };
 
-                if let Some(ref node) = *node {
-                    node.serialize_node(&mut s)
+                for child in children.iter() {
+                    child.serialize_node(&mut s);
                 }
             }
             _ => panic!("expected document, got: {:?}", self),
@@ -69,6 +132,7 @@ impl SerializeDocument for Handle {
 impl<'a> SerializeNode for &'a Handle {
     fn serialize_node<W: Write>(self, s: &mut NodeSerializer<W>) {
         match *self.borrow() {
+            (Node::CData(ref text), _) => s.cdata(&text),
             (Node::Comment(ref comment), _) => s.comment(&comment),
             (Node::Element(ref name, ref attributes, ref children), _) => {
                 let mut node = s.element(name.clone(), attributes.iter().map(|a| (&a.0, &a.1[..])));
@@ -76,15 +140,29 @@ impl<'a> SerializeNode for &'a Handle {
                 for child in children.iter() {
                     child.serialize_node(&mut node);
                 }
+
+                if let Some(ref contents) = self.0.template_contents {
+                    contents.serialize_node(&mut node);
+                }
+            }
+            (Node::ProcessingInstruction(ref target, ref data), _) => {
+                s.processing_instruction(&target, &data)
+            }
+            (Node::TemplateContents(ref children), _) => {
+                for child in children.iter() {
+                    child.serialize_node(s);
+                }
             }
             (Node::Text(ref text), _) => s.text(&text),
-            _ => panic!("expected comment, element or text, got: {:?}", self),
+            _ => panic!("expected cdata, comment, element, processing instruction, \
+                         template contents or text, got: {:?}",
+                        self),
         }
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct WeakHandle(Weak<RefCell<(Node, Option<WeakHandle>)>>);
+pub struct WeakHandle(Weak<Inner>);
 
 impl WeakHandle {
     pub fn upgrade(&self) -> Handle {
@@ -92,10 +170,18 @@ impl WeakHandle {
     }
 }
 
-impl Deref for WeakHandle {
-    type Target = Weak<RefCell<(Node, Option<WeakHandle>)>>;
-
-    fn deref(&self) -> &Weak<RefCell<(Node, Option<WeakHandle>)>> {
-        &self.0
-    }
+// Template elements in the HTML namespace get an empty contents fragment.
+pub fn new_handle(node: Node, position: Option<Position>) -> Handle {
+    let template_contents = match node {
+        Node::Element(ref name, _, _) if *name == qualname!(html, "template") => {
+            Some(new_handle(Node::TemplateContents(Vec::new()), position))
+        }
+        _ => None,
+    };
+
+    Handle(Rc::new(Inner {
+        node: RefCell::new((node, None)),
+        position: position,
+        template_contents: template_contents,
+    }))
 }
@@ -0,0 +1,247 @@
+
+use std::vec;
+
+use super::{Handle, Node};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edge {
+    Open(Handle),
+    Close(Handle),
+}
+
+impl Handle {
+    pub fn children(&self) -> Children {
+        Children(child_nodes(self).into_iter())
+    }
+
+    pub fn descendants(&self) -> Descendants {
+        let mut stack = child_nodes(self);
+        stack.reverse();
+
+        Descendants(stack)
+    }
+
+    pub fn ancestors(&self) -> Ancestors {
+        Ancestors(self.parent())
+    }
+
+    pub fn following_siblings(&self) -> Siblings {
+        let siblings = match self.parent() {
+            Some(parent) => {
+                let mut siblings = child_nodes(&parent);
+                let index = position(&siblings, self) + 1;
+                siblings.split_off(index)
+            }
+            None => Vec::new(),
+        };
+
+        Siblings(siblings.into_iter())
+    }
+
+    pub fn preceding_siblings(&self) -> Siblings {
+        let siblings = match self.parent() {
+            Some(parent) => {
+                let mut siblings = child_nodes(&parent);
+                let index = position(&siblings, self);
+                siblings.truncate(index);
+                siblings.reverse();
+                siblings
+            }
+            None => Vec::new(),
+        };
+
+        Siblings(siblings.into_iter())
+    }
+
+    pub fn traverse(&self) -> Traverse {
+        Traverse(vec![Edge::Open(self.clone())])
+    }
+}
+
+pub struct Children(vec::IntoIter<Handle>);
+
+impl Iterator for Children {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        self.0.next()
+    }
+}
+
+impl DoubleEndedIterator for Children {
+    fn next_back(&mut self) -> Option<Handle> {
+        self.0.next_back()
+    }
+}
+
+// Nodes are yielded in pre-order, the stack holds the nodes that are left to visit in reverse.
+pub struct Descendants(Vec<Handle>);
+
+impl Iterator for Descendants {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        let next = self.0.pop();
+
+        if let Some(ref next) = next {
+            self.0.extend(child_nodes(next).into_iter().rev());
+        }
+
+        next
+    }
+}
+
+pub struct Ancestors(Option<Handle>);
+
+impl Iterator for Ancestors {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        let next = self.0.take();
+
+        if let Some(ref next) = next {
+            self.0 = next.parent();
+        }
+
+        next
+    }
+}
+
+// Siblings are yielded starting with the one closest to the node.
+pub struct Siblings(vec::IntoIter<Handle>);
+
+impl Iterator for Siblings {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        self.0.next()
+    }
+}
+
+pub struct Traverse(Vec<Edge>);
+
+impl Iterator for Traverse {
+    type Item = Edge;
+
+    fn next(&mut self) -> Option<Edge> {
+        let next = self.0.pop();
+
+        if let Some(Edge::Open(ref handle)) = next {
+            self.0.push(Edge::Close(handle.clone()));
+            self.0.extend(child_nodes(handle).into_iter().rev().map(Edge::Open));
+        }
+
+        next
+    }
+}
+
+// Yields the given nodes and their descendants, in pre-order.
+pub fn inclusive_descendants(roots: &[Handle]) -> Descendants {
+    Descendants(roots.iter().rev().cloned().collect())
+}
+
+fn child_nodes(handle: &Handle) -> Vec<Handle> {
+    match handle.borrow().0 {
+        Node::Document(ref children) |
+        Node::Element(_, _, ref children) |
+        Node::TemplateContents(ref children) => children.clone(),
+        _ => Vec::new(),
+    }
+}
+
+pub fn position(siblings: &[Handle], handle: &Handle) -> usize {
+    siblings.iter()
+            .position(|s| s == handle)
+            .expect("node is not a child of its parent")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::{Dom, Handle, Node};
+
+    use html5ever::tree_builder::{TreeSink, NodeOrText};
+
+    fn name(handle: &Handle) -> String {
+        match handle.borrow().0 {
+            Node::Element(ref name, _, _) => name.local.to_string(),
+            Node::Text(ref text) => text.to_string(),
+            _ => panic!("expected element or text, got: {:?}", handle),
+        }
+    }
+
+    fn names<I: Iterator<Item = Handle>>(iter: I) -> Vec<String> {
+        iter.map(|h| name(&h)).collect()
+    }
+
+    // <div><p>a</p><br><span>b<br></span></div>
+    fn tree() -> (Handle, Vec<Handle>) {
+        let mut dom = Dom::new();
+        let div = dom.create_element(qualname!(html, "div"), Vec::new());
+        let p = dom.create_element(qualname!(html, "p"), Vec::new());
+        let br_a = dom.create_element(qualname!(html, "br"), Vec::new());
+        let span = dom.create_element(qualname!(html, "span"), Vec::new());
+        let br_b = dom.create_element(qualname!(html, "br"), Vec::new());
+
+        dom.append(div.clone(), NodeOrText::AppendNode(p.clone()));
+        dom.append(p.clone(), NodeOrText::AppendText("a".into()));
+        dom.append(div.clone(), NodeOrText::AppendNode(br_a.clone()));
+        dom.append(div.clone(), NodeOrText::AppendNode(span.clone()));
+        dom.append(span.clone(), NodeOrText::AppendText("b".into()));
+        dom.append(span.clone(), NodeOrText::AppendNode(br_b.clone()));
+
+        (div, vec![p, br_a, span, br_b])
+    }
+
+    #[test]
+    fn test_children() {
+        let (div, _) = tree();
+
+        assert_eq!(names(div.children()), &["p", "br", "span"]);
+        assert_eq!(names(div.children().rev()), &["span", "br", "p"]);
+    }
+
+    #[test]
+    fn test_descendants() {
+        let (div, _) = tree();
+
+        assert_eq!(names(div.descendants()), &["p", "a", "br", "span", "b", "br"]);
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let (div, nodes) = tree();
+        let br_b = &nodes[3];
+
+        assert_eq!(names(br_b.ancestors()), &["span", "div"]);
+        assert_eq!(names(div.ancestors()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_siblings() {
+        let (div, nodes) = tree();
+        let br_a = &nodes[1];
+
+        assert_eq!(names(br_a.following_siblings()), &["span"]);
+        assert_eq!(names(br_a.preceding_siblings()), &["p"]);
+        assert_eq!(names(nodes[2].following_siblings()), Vec::<String>::new());
+        assert_eq!(names(div.preceding_siblings()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_traverse() {
+        let (div, _) = tree();
+
+        let edges: Vec<_> = div.traverse()
+                               .map(|e| {
+                                   match e {
+                                       Edge::Open(h) => format!("<{}>", name(&h)),
+                                       Edge::Close(h) => format!("</{}>", name(&h)),
+                                   }
+                               })
+                               .collect();
+
+        assert_eq!(edges.concat(),
+                   "<div><p><a></a></p><br></br><span><b></b><br></br></span></div>");
+    }
+}
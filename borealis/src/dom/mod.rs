@@ -1,35 +1,60 @@
 
 use std::borrow::Cow;
-use std::cell::Ref;
+use std::cell::Cell;
 use std::collections::HashSet;
+use std::rc::Rc;
 
 use html5ever::Attribute;
-use html5ever::tree_builder::{TreeSink, QuirksMode, NodeOrText};
+use html5ever::tree_builder::{TreeSink, NodeOrText};
 use html5ever::tendril::{StrTendril, TendrilSink};
 
 use string_cache::QualName;
 
+pub use self::arena::{Arena, ArenaNode, NodeData, NodeId};
+pub use self::compare::CompareOpts;
+pub use self::diff::{diff, Diff, Edit, NodePath};
 pub use self::document::Document;
 pub use self::fragment::Fragment;
 pub use self::handle::{Node, Handle, WeakHandle};
+pub use self::iter::{Ancestors, Children, Descendants, Edge, Siblings, Traverse};
+pub use self::parse::{Parsed, ParseError, Position};
+pub use self::sanitize::SanitizePolicy;
+pub use self::select::{Select, SelectorError, Selectors};
 
+pub use html5ever::tree_builder::QuirksMode;
+
+use self::handle::new_handle;
+
+mod arena;
+mod compare;
+mod decode;
+mod diff;
 mod document;
 mod fragment;
 mod handle;
+mod iter;
+mod mutate;
+mod parse;
+mod sanitize;
+mod select;
+#[cfg(feature = "serde")]
+mod serde;
 
 #[derive(Debug)]
 pub struct Dom {
     document: Handle,
-    errors: Vec<Cow<'static, str>>,
+    errors: Vec<ParseError>,
     quirks_mode: QuirksMode,
+    position: Rc<Cell<Option<Position>>>,
 }
 
 impl Dom {
     pub fn new() -> Dom {
         Dom {
-            document: Node::Document(None, None).into(),
+            document: Node::Document(Vec::new()).into(),
             errors: Vec::new(),
             quirks_mode: QuirksMode::NoQuirks,
+            position: Rc::new(Cell::new(None)),
         }
     }
 
@@ -37,47 +62,55 @@ impl Dom {
         self.document.clone()
     }
 
-    pub fn fragment(&self) -> Vec<Handle> {
-        fn element_children<'a>(node: &'a Ref<(Node, Option<WeakHandle>)>) -> &'a [Handle] {
-            match **node {
-                (Node::Element(_, _, ref children), _) => &children,
-                _ => panic!("expected element, got: {:?}", node),
-            }
-        }
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    // The tree builder in this version of html5ever never creates these, they are here for
+    // building foreign content with the same sink.
+    pub fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+        self.create_handle(Node::ProcessingInstruction(target, data))
+    }
 
-        fn is_user_element(name: &QualName) -> bool {
-            match *name {
-                qualname!(html, "head") => false,
-                qualname!(html, "body") => false,
-                _ => true,
+    pub fn create_cdata(&mut self, text: StrTendril) -> Handle {
+        self.create_handle(Node::CData(text))
+    }
+
+    pub fn fragment(&self) -> Vec<Handle> {
+        fn is_element(node: &Handle) -> bool {
+            match *node.borrow() {
+                (Node::Element(..), _) => true,
+                _ => false,
             }
         }
 
         let html = match *self.document.borrow() {
-            (Node::Document(_, ref child), _) => child.clone().unwrap(),
+            (Node::Document(ref children), _) => {
+                children.iter().find(|c| is_element(c)).cloned().unwrap()
+            }
             _ => panic!("expected document, got: {:?}", self.document),
         };
 
-        let mut children = Vec::new();
-        for child in element_children(&html.borrow()).iter() {
-            match *child.borrow() {
-                (Node::Element(ref name, _, _), _) => {
-                    if is_user_element(name) {
-                        children.push(child.clone());
-                    }
-                }
-                (Node::Comment(..), _) => children.push(child.clone()),
-                (Node::Text(..), _) => children.push(child.clone()),
-                _ => panic!("expected element, got: {:?}", child),
-            }
-        }
+        let children = match *html.borrow() {
+            (Node::Element(_, _, ref children), _) => children.clone(),
+            _ => panic!("expected element, got: {:?}", html),
+        };
 
         children
     }
 
-    fn node_or_text_as_handle(child: &NodeOrText<Handle>) -> Handle {
+    // Creates a handle positioned where the parser currently is.
+    fn create_handle(&self, node: Node) -> Handle {
+        new_handle(node, self.position.get())
+    }
+
+    fn node_or_text_as_handle(&self, child: &NodeOrText<Handle>) -> Handle {
         match child {
-            &NodeOrText::AppendText(ref text) => Node::Text(text.clone()).into(),
+            &NodeOrText::AppendText(ref text) => self.create_handle(Node::Text(text.clone())),
             &NodeOrText::AppendNode(ref node) => node.clone(),
         }
     }
@@ -92,7 +125,10 @@ impl TreeSink for Dom {
     }
 
     fn parse_error(&mut self, message: Cow<'static, str>) {
-        self.errors.push(message);
+        self.errors.push(ParseError {
+            message: message,
+            position: self.position.get(),
+        });
     }
 
     fn get_document(&mut self) -> Handle {
@@ -100,7 +136,10 @@ impl TreeSink for Dom {
     }
 
     fn get_template_contents(&self, target: Handle) -> Handle {
-        target
+        match target.template_contents() {
+            Some(contents) => contents,
+            None => panic!("expected template, got: {:?}", target),
+        }
     }
 
     fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
@@ -120,25 +159,28 @@ impl TreeSink for Dom {
 
     fn create_element(&mut self, name: QualName, attributes: Vec<Attribute>) -> Handle {
         let attributes = attributes.into_iter().map(|a| (a.name, a.value)).collect();
-        Node::Element(name, attributes, Vec::new()).into()
+        self.create_handle(Node::Element(name, attributes, Vec::new()))
     }
 
     fn create_comment(&mut self, text: StrTendril) -> Handle {
-        Node::Comment(text).into()
+        self.create_handle(Node::Comment(text))
     }
 
     fn append(&mut self, parent: Handle, child: NodeOrText<Handle>) {
         match *parent.borrow_mut() {
-            (Node::Document(_, ref mut old_child), _) => {
-                assert!(old_child.is_none());
-
-                let child = Dom::node_or_text_as_handle(&child);
-                child.borrow_mut().1 = Some(parent.downgrade());
+            (Node::Document(ref mut children), _) |
+            (Node::Element(_, _, ref mut children), _) |
+            (Node::TemplateContents(ref mut children), _) => {
+                if let NodeOrText::AppendText(ref text) = child {
+                    if let Some(last) = children.last() {
+                        if let Node::Text(ref mut last_text) = last.borrow_mut().0 {
+                            last_text.push_tendril(text);
+                            return;
+                        }
+                    }
+                }
 
-                *old_child = Some(child);
-            }
-            (Node::Element(_, _, ref mut children), _) => {
-                let child = Dom::node_or_text_as_handle(&child);
+                let child = self.node_or_text_as_handle(&child);
                 child.borrow_mut().1 = Some(parent.downgrade());
 
                 children.push(child);
@@ -152,29 +194,46 @@ impl TreeSink for Dom {
                              child: NodeOrText<Handle>)
                              -> Result<(), NodeOrText<Handle>> {
         let (_, ref parent) = *sibling.borrow();
-        let child = Dom::node_or_text_as_handle(&child);
-        child.borrow_mut().1 = parent.clone();
+        let node = self.node_or_text_as_handle(&child);
+        node.borrow_mut().1 = parent.clone();
 
         let parent = parent.clone().unwrap().upgrade();
 
         match *parent.borrow_mut() {
-            (Node::Element(_, _, ref mut children), _) => {
+            (Node::Document(ref mut children), _) |
+            (Node::Element(_, _, ref mut children), _) |
+            (Node::TemplateContents(ref mut children), _) => {
                 let index = children.iter()
                                     .position(|e| *e == sibling)
                                     .unwrap();
 
-                children.insert(index, child);
+                if let NodeOrText::AppendText(ref text) = child {
+                    if index > 0 {
+                        if let Node::Text(ref mut previous) = children[index - 1].borrow_mut().0 {
+                            previous.push_tendril(text);
+                            return Ok(());
+                        }
+                    }
+                }
+
+                children.insert(index, node);
             }
-            _ => panic!("expected element, got: {:?}", parent),
+            _ => panic!("expected document or element, got: {:?}", parent),
         }
 
         Ok(())
     }
 
-    fn append_doctype_to_document(&mut self, name: StrTendril, _: StrTendril, _: StrTendril) {
+    fn append_doctype_to_document(&mut self,
+                                  name: StrTendril,
+                                  public_id: StrTendril,
+                                  system_id: StrTendril) {
+        let node = self.create_handle(Node::Doctype(name, public_id, system_id));
+        node.borrow_mut().1 = Some(self.document.downgrade());
+
         match *self.document.borrow_mut() {
-            (Node::Document(ref mut doctype, _), _) => {
-                *doctype = Some(name);
+            (Node::Document(ref mut children), _) => {
+                children.push(node);
             }
             _ => panic!("expected document"),
         }
@@ -207,11 +266,9 @@ impl TreeSink for Dom {
         };
 
         match *parent.borrow_mut() {
-            (Node::Document(_, ref mut old_child), _) => {
-                assert_eq!(old_child.as_ref(), Some(&target));
-                *old_child = None;
-            }
-            (Node::Element(_, _, ref mut children), _) => {
+            (Node::Document(ref mut children), _) |
+            (Node::Element(_, _, ref mut children), _) |
+            (Node::TemplateContents(ref mut children), _) => {
                 let index = children.iter()
                                     .position(|e| *e == target)
                                     .unwrap();
@@ -223,14 +280,9 @@ impl TreeSink for Dom {
 
     fn reparent_children(&mut self, old_parent: Handle, new_parent: Handle) {
         match *old_parent.borrow_mut() {
-            (Node::Document(_, ref mut child), _) => {
-                if let &mut Some(ref child) = child {
-                    self.append(new_parent, NodeOrText::AppendNode(child.clone()));
-                }
-
-                *child = None;
-            }
-            (Node::Element(_, _, ref mut children), _) => {
+            (Node::Document(ref mut children), _) |
+            (Node::Element(_, _, ref mut children), _) |
+            (Node::TemplateContents(ref mut children), _) => {
                 for child in children.iter() {
                     let child = NodeOrText::AppendNode(child.clone());
                     self.append(new_parent.clone(), child);
@@ -280,8 +332,146 @@ mod tests {
         dom.append(html.clone(), NodeOrText::AppendNode(element.clone()));
 
         let fragment = dom.fragment();
-        assert!(fragment.len() == 1);
-        assert_eq!(fragment[0], element);
+        assert_eq!(&fragment[..], &[head, body, element]);
+    }
+
+    #[test]
+    fn test_fragment_context() {
+        use string_cache::Atom;
+
+        fn element_name(handle: &Handle) -> QualName {
+            match *handle.borrow() {
+                (Node::Element(ref name, _, _), _) => name.clone(),
+                _ => panic!("expected element, got: {:?}", handle),
+            }
+        }
+
+        let rows = "<tr><td>a</td></tr><tr><td>b</td></tr>";
+
+        let fragment = Fragment::parse_str(rows).handles();
+        assert!(fragment.iter().all(|h| {
+            match *h.borrow() {
+                (Node::Text(..), _) => true,
+                _ => false,
+            }
+        }));
+
+        let fragment = Fragment::parse_str_in_context(rows, qualname!(html, "tbody")).handles();
+        assert_eq!(fragment.len(), 2);
+        assert!(fragment.iter().all(|h| element_name(h) == qualname!(html, "tr")));
+
+        let circle = "<circle r=\"1\"/>";
+        let fragment = Fragment::parse_str_in_context(circle, qualname!(svg, "svg")).handles();
+        assert_eq!(fragment.len(), 1);
+        assert_eq!(element_name(&fragment[0]),
+                   QualName::new(ns!(svg), Atom::from("circle")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parsed = Document::parse_str_with_errors("<!DOCTYPE html>\n<p>\n</x>");
+        let position = Position {
+            line: 3,
+            column: 1,
+            offset: 20,
+        };
+
+        assert_eq!(parsed.quirks_mode, QuirksMode::NoQuirks);
+        assert!(parsed.errors.iter().any(|e| e.position == Some(position)));
+        assert!(Document::parse_str_strict("<!DOCTYPE html>\n<p>\n</x>").is_err());
+
+        let parsed = Document::parse_str_with_errors("<!DOCTYPE html><title>a</title>");
+
+        assert!(parsed.errors.is_empty());
+        assert!(Document::parse_str_strict("<!DOCTYPE html><title>a</title>").is_ok());
+
+        let parsed = Document::parse_str_with_errors("<p>a</p>");
+
+        assert_eq!(parsed.quirks_mode, QuirksMode::Quirks);
+        assert!(!parsed.errors.is_empty());
+
+        assert!(Fragment::parse_str_strict("<p>a</p>").is_ok());
+        assert!(Fragment::parse_str_strict("<p>a</x>").is_err());
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        use enc::EncodingRef;
+        use enc::all::{ISO_8859_2, UTF_8};
+        use serializer;
+
+        fn parse(bytes: &[u8], encoding: Option<EncodingRef>) -> String {
+            serializer::to_string(Document::parse_bytes(bytes, encoding)).unwrap()
+        }
+
+        assert!(parse(b"<p>caf\xc3\xa9</p>", Some(UTF_8 as EncodingRef))
+                    .contains("<p>café</p>"));
+        assert!(parse(b"<p>caf\xe9</p>", None).contains("<p>café</p>"));
+        assert!(parse(b"\xef\xbb\xbf<p>caf\xc3\xa9</p>", Some(ISO_8859_2 as EncodingRef))
+                    .contains("<p>café</p>"));
+        assert!(parse(b"<meta charset=\"utf-8\"><p>caf\xc3\xa9</p>", None)
+                    .contains("<p>café</p>"));
+        assert!(parse(b"<meta http-equiv=\"Content-Type\" \
+                        content=\"text/html; charset=ISO-8859-2\"><p>\xb1</p>",
+                      None)
+                    .contains("<p>ą</p>"));
+        assert!(parse(b"<!-- <meta charset=\"utf-8\"> --><p>caf\xe9</p>", None)
+                    .contains("<p>café</p>"));
+
+        let fragment = Fragment::parse_reader(&b"<p>a</p><p>b</p>"[..], None).unwrap();
+        assert_eq!(fragment.handles().len(), 2);
+
+        let fragment = Fragment::parse_bytes(b"<p>caf\xc3\xa9</p>", None);
+        assert_eq!(fragment.handles()[0].children().next().unwrap().borrow().0,
+                   Node::Text("café".into()));
+    }
+
+    #[test]
+    fn test_parse_split_sequences() {
+        use std::iter;
+
+        use serializer;
+
+        // The first 1024 bytes are decoded on their own, so `head` ends the first chunk and
+        // `tail` starts the next one.
+        fn parse(charset: &str, head: &[u8], tail: &[u8]) -> String {
+            let prefix = format!("<meta charset=\"{}\"><!--", charset);
+            let suffix = "--><p>a";
+            let padding = 1024 - prefix.len() - suffix.len() - head.len();
+
+            let mut bytes = prefix.into_bytes();
+            bytes.extend(iter::repeat(b' ').take(padding));
+            bytes.extend_from_slice(suffix.as_bytes());
+            bytes.extend_from_slice(head);
+            bytes.extend_from_slice(tail);
+
+            serializer::to_string(Document::parse_bytes(&bytes, None)).unwrap()
+        }
+
+        assert!(parse("utf-8", b"\xe2\x82", b"(b</p>").contains("<p>a\u{fffd}(b</p>"));
+        assert!(parse("utf-8", b"\xe2\x82", b"\xac</p>").contains("<p>a\u{20ac}</p>"));
+        assert!(parse("gb18030", b"\x81\x30", b" b</p>").contains("<p>a\u{fffd}0 b</p>"));
+    }
+
+    #[test]
+    fn test_node_positions() {
+        let document = Document::parse_str_with_errors("<!DOCTYPE html>\n<p>a <b>c</b></p>").output;
+        let p = document.select_first("p").unwrap().unwrap();
+        let b = document.select_first("b").unwrap().unwrap();
+        let position = |line, column, offset| {
+            Some(Position {
+                line: line,
+                column: column,
+                offset: offset,
+            })
+        };
+
+        assert_eq!(p.position(), position(2, 1, 16));
+        assert_eq!(p.children().next().unwrap().position(), position(2, 4, 19));
+        assert_eq!(b.position(), position(2, 6, 21));
+
+        let document = Document::parse_str("<p>a</p>");
+        assert_eq!(document.select_first("p").unwrap().unwrap().position(), None);
     }
 
     #[test]
@@ -292,9 +482,44 @@ mod tests {
         let document = dom.get_document();
 
         dom.append(document.clone(), NodeOrText::AppendNode(template.clone()));
-        dom.append(template.clone(), NodeOrText::AppendNode(element.clone()));
 
-        assert_eq!(dom.get_template_contents(template.clone()), template);
+        let contents = dom.get_template_contents(template.clone());
+        dom.append(contents.clone(), NodeOrText::AppendNode(element.clone()));
+
+        match *contents.borrow() {
+            (Node::TemplateContents(ref children), ref parent) => {
+                assert_eq!(&children[..], &[element.clone()]);
+                assert!(parent.is_none());
+            }
+            _ => panic!("contents is not template contents: {:?}", contents),
+        }
+
+        match *template.borrow() {
+            (Node::Element(_, _, ref children), _) => assert!(children.is_empty()),
+            _ => panic!("template is not an element: {:?}", template),
+        }
+
+        assert_eq!(template.template_contents(), Some(contents));
+        assert_eq!(element.template_contents(), None);
+    }
+
+    #[test]
+    fn test_template_contents_are_not_children() {
+        use serializer;
+
+        let document = Document::parse_str("<template><p>a</p></template><p>b</p>");
+        let template = document.select_first("template").unwrap().unwrap();
+
+        assert_eq!(template.children().count(), 0);
+        assert_eq!(template.descendants().count(), 0);
+        assert_eq!(document.select("p").unwrap().count(), 1);
+
+        template.set_text("c".into());
+
+        assert_eq!(template.template_contents().unwrap().children().count(), 1);
+        assert!(serializer::to_string(document)
+                    .unwrap()
+                    .contains("<template>c<p>a</p></template>"));
     }
 
     #[test]
@@ -328,6 +553,7 @@ mod tests {
              ref elem_parent) => {
                 assert_eq!(name, *elem_name);
                 assert_eq!(attrs, &elem_attrs[..]);
+
                 assert!(elem_children.is_empty());
                 assert!(elem_parent.is_none());
             }
@@ -368,6 +594,54 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_create_pi_and_cdata() {
+        use serializer;
+
+        let mut dom = Dom::new();
+        let document = dom.get_document();
+        let svg = dom.create_element(qualname!(svg, "svg"), Vec::new());
+        let pi = dom.create_pi("a".into(), "b".into());
+        let cdata = dom.create_cdata("<c>".into());
+
+        dom.append(document.clone(), NodeOrText::AppendNode(svg.clone()));
+        dom.append(svg.clone(), NodeOrText::AppendNode(pi.clone()));
+        dom.append(svg.clone(), NodeOrText::AppendNode(cdata.clone()));
+
+        assert!(pi.deep_eq(&Node::ProcessingInstruction("a".into(), "b".into()).into()));
+        assert!(cdata.deep_eq(&Node::CData("<c>".into()).into()));
+        assert_eq!(serializer::to_string(document).unwrap(),
+                   "<svg><?a b><![CDATA[<c>]]></svg>");
+    }
+
+    #[test]
+    fn test_foreign_content() {
+        use serializer;
+
+        // The parser never creates processing instructions or CDATA sections, those nodes can
+        // only be built by hand.
+        let svg = Fragment::parse_str("<svg><![CDATA[<c>]]><?a b></svg>").handles()[0].clone();
+        let children = svg.children();
+
+        assert!(!children.is_empty());
+        for child in children.iter() {
+            match *child.borrow() {
+                (Node::Text(_), _) | (Node::Comment(_), _) => {}
+                _ => panic!("expected text or comment, got: {:?}", child),
+            }
+        }
+
+        for child in children {
+            child.detach();
+        }
+
+        svg.append_child(Node::ProcessingInstruction("a".into(), "b".into()).into());
+        svg.append_child(Node::CData("<c>".into()).into());
+
+        assert_eq!(serializer::to_string(&svg).unwrap(),
+                   "<svg><?a b><![CDATA[<c>]]></svg>");
+    }
+
     #[cfg(feature = "nightly")]
     #[bench]
     fn bench_create_comment(b: &mut Bencher) {
@@ -397,8 +671,8 @@ mod tests {
             }
 
             match *document.borrow() {
-                (Node::Document(_, ref child), _) => {
-                    assert_eq!(html, child.clone().unwrap());
+                (Node::Document(ref children), _) => {
+                    assert_eq!(&children[..], &[html.clone()]);
                 }
                 _ => panic!("document is not a document: {:?}", document),
             }
@@ -423,6 +697,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_append_text() {
+        let mut dom = Dom::new();
+        let div = dom.create_element(qualname!(html, "div"), Vec::new());
+        let br = dom.create_element(qualname!(html, "br"), Vec::new());
+
+        dom.append(div.clone(), NodeOrText::AppendText("a".into()));
+        dom.append(div.clone(), NodeOrText::AppendText("b".into()));
+        dom.append(div.clone(), NodeOrText::AppendNode(br.clone()));
+        assert!(dom.append_before_sibling(br.clone(), NodeOrText::AppendText("c".into())).is_ok());
+
+        match *div.borrow() {
+            (Node::Element(_, _, ref children), _) => {
+                assert_eq!(children.len(), 2);
+                assert!(children[0].deep_eq(&Node::Text("abc".into()).into()));
+                assert_eq!(children[1], br);
+            }
+            _ => panic!("div is not an element: {:?}", div),
+        };
+
+        let fragment = Fragment::parse_str("a &amp; b &lt; c").handles();
+        assert_eq!(fragment.len(), 1);
+    }
+
     #[test]
     fn test_append_before_sibling() {
         let mut dom = Dom::new();
@@ -445,6 +743,32 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_identical_siblings() {
+        let mut dom = Dom::new();
+        let div = dom.create_element(qualname!(html, "div"), Vec::new());
+        let br_a = dom.create_element(qualname!(html, "br"), Vec::new());
+        let br_b = dom.create_element(qualname!(html, "br"), Vec::new());
+        let p = dom.create_element(qualname!(html, "p"), Vec::new());
+
+        dom.append(div.clone(), NodeOrText::AppendNode(br_a.clone()));
+        dom.append(div.clone(), NodeOrText::AppendNode(br_b.clone()));
+
+        assert!(!dom.same_node(br_a.clone(), br_b.clone()));
+        assert!(br_a.deep_eq(&br_b));
+
+        assert!(dom.append_before_sibling(br_b.clone(), NodeOrText::AppendNode(p.clone()))
+                   .is_ok());
+        dom.remove_from_parent(br_b.clone());
+
+        match *div.borrow() {
+            (Node::Element(_, _, ref children), _) => {
+                assert_eq!(&children[..], &[br_a, p]);
+            }
+            _ => panic!("div is not an element: {:?}", div),
+        };
+    }
+
     #[test]
     fn test_append_doctype_to_document() {
         let mut dom = Dom::new();
@@ -455,13 +779,49 @@ mod tests {
 
         dom.append_doctype_to_document(name.clone(), public_id.clone(), system_id.clone());
 
+        let doctype = match *document.borrow() {
+            (Node::Document(ref children), _) => children[0].clone(),
+            _ => panic!("document is not a document: {:?}", document),
+        };
+
+        match *doctype.borrow() {
+            (Node::Doctype(ref doctype_name, ref doctype_public_id, ref doctype_system_id),
+             ref doctype_parent) => {
+                assert_eq!(*doctype_name, name);
+                assert_eq!(*doctype_public_id, public_id);
+                assert_eq!(*doctype_system_id, system_id);
+                assert_eq!(document, doctype_parent.clone().unwrap().upgrade());
+            }
+            _ => panic!("doctype is not a doctype: {:?}", doctype),
+        };
+    }
+
+    #[test]
+    fn test_append_to_document() {
+        let mut dom = Dom::new();
+        let document = dom.get_document();
+        let before = dom.create_comment(StrTendril::from("before".to_owned()));
+        let html = dom.create_element(qualname!(html, "html"), Vec::new());
+        let after = dom.create_comment(StrTendril::from("after".to_owned()));
+
+        dom.append(document.clone(), NodeOrText::AppendNode(before.clone()));
+        dom.append_doctype_to_document("html".into(), "".into(), "".into());
+        dom.append(document.clone(), NodeOrText::AppendNode(html.clone()));
+        dom.append(document.clone(), NodeOrText::AppendNode(after.clone()));
+
         match *document.borrow() {
-            (Node::Document(ref doctype, _), _) => {
-                let doctype = doctype.as_ref().unwrap();
-                assert_eq!(*doctype, name);
+            (Node::Document(ref children), _) => {
+                assert_eq!(children.len(), 4);
+                assert_eq!(children[0], before);
+                assert!(children[1]
+                            .deep_eq(&Node::Doctype("html".into(), "".into(), "".into()).into()));
+                assert_eq!(children[2], html);
+                assert_eq!(children[3], after);
             }
             _ => panic!("document is not a document: {:?}", document),
         };
+
+        assert!(dom.fragment().is_empty());
     }
 
     #[test]
@@ -505,8 +865,8 @@ mod tests {
         dom.remove_from_parent(html.clone());
 
         match *document.borrow() {
-            (Node::Document(_, ref child), _) => {
-                assert!(child.is_none());
+            (Node::Document(ref children), _) => {
+                assert!(children.is_empty());
             }
             _ => panic!("document is not a document: {:?}", document),
         }
@@ -516,8 +876,8 @@ mod tests {
         dom.remove_from_parent(body.clone());
 
         match *document.borrow() {
-            (Node::Document(_, ref child), _) => {
-                assert_eq!(*child, Some(html));
+            (Node::Document(ref children), _) => {
+                assert_eq!(&children[..], &[html]);
             }
             _ => panic!("document is not a document: {:?}", document),
         };
@@ -556,8 +916,8 @@ mod tests {
         dom.reparent_children(document.clone(), target.clone());
 
         match *document.borrow() {
-            (Node::Document(_, ref child), _) => {
-                assert!(child.is_none());
+            (Node::Document(ref children), _) => {
+                assert!(children.is_empty());
             }
             _ => panic!("document is not a document: {:?}", document),
         }
@@ -0,0 +1,390 @@
+
+use std::mem;
+
+use html5ever::tendril::StrTendril;
+
+use string_cache::QualName;
+
+use url;
+use super::{Handle, Node};
+use super::iter::{inclusive_descendants, position};
+
+impl Handle {
+    pub fn append_child(&self, child: Handle) {
+        check_insertion(self, &child);
+
+        child.detach();
+        child.borrow_mut().1 = Some(self.downgrade());
+
+        with_children(self, |children| children.push(child));
+    }
+
+    // Inserts `sibling` right before this node, which must have a parent.
+    pub fn insert_before(&self, sibling: Handle) {
+        if *self == sibling {
+            return;
+        }
+
+        let parent = self.parent().expect("cannot insert next to a node without a parent");
+        check_insertion(&parent, &sibling);
+
+        sibling.detach();
+        sibling.borrow_mut().1 = Some(parent.downgrade());
+
+        with_children(&parent, |children| {
+            let index = position(children, self);
+            children.insert(index, sibling);
+        });
+    }
+
+    pub fn replace_with(&self, node: Handle) {
+        if *self != node {
+            self.insert_before(node);
+            self.detach();
+        }
+    }
+
+    // Unlinks the node from its parent, it can then be inserted somewhere else.
+    pub fn detach(&self) {
+        let parent = self.parent();
+        self.borrow_mut().1 = None;
+
+        if let Some(parent) = parent {
+            with_children(&parent, |children| {
+                let index = position(children, self);
+                children.remove(index);
+            });
+        }
+    }
+
+    // Detaches the node and its children, leaving any other handle to them without a parent.
+    pub fn remove(self) {
+        self.detach();
+
+        for child in self.children() {
+            child.detach();
+        }
+    }
+
+    pub fn set_attribute(&self, name: QualName, value: StrTendril) {
+        match self.borrow_mut().0 {
+            Node::Element(_, ref mut attributes, _) => {
+                let index = attributes.iter().position(|a| a.0 == name);
+
+                match index {
+                    Some(index) => attributes[index].1 = value,
+                    None => attributes.push((name, value)),
+                }
+            }
+            _ => panic!("expected element, got: {:?}", self),
+        }
+    }
+
+    pub fn remove_attribute(&self, name: &QualName) -> Option<StrTendril> {
+        match self.borrow_mut().0 {
+            Node::Element(_, ref mut attributes, _) => {
+                let index = attributes.iter().position(|a| a.0 == *name);
+                index.map(|i| attributes.remove(i).1)
+            }
+            _ => panic!("expected element, got: {:?}", self),
+        }
+    }
+
+    // Merges adjacent text nodes and removes empty ones, in this node and all of its descendants.
+    pub fn normalize(&self) {
+        let children = match self.borrow().0 {
+            Node::Document(ref children) |
+            Node::Element(_, _, ref children) |
+            Node::TemplateContents(ref children) => children.clone(),
+            _ => return,
+        };
+
+        let mut normalized: Vec<Handle> = Vec::with_capacity(children.len());
+
+        for child in children {
+            let text = match child.borrow().0 {
+                Node::Text(ref text) => Some(text.clone()),
+                _ => None,
+            };
+
+            match text {
+                Some(text) => {
+                    if let Some(last) = normalized.last() {
+                        if let Node::Text(ref mut last_text) = last.borrow_mut().0 {
+                            last_text.push_tendril(&text);
+                            child.borrow_mut().1 = None;
+                            continue;
+                        }
+                    }
+
+                    if text.is_empty() {
+                        child.borrow_mut().1 = None;
+                        continue;
+                    }
+                }
+                None => child.normalize(),
+            }
+
+            normalized.push(child);
+        }
+
+        with_children(self, |children| *children = normalized);
+
+        if let Some(contents) = self.template_contents() {
+            contents.normalize();
+        }
+    }
+
+    // Rewrites the URLs in the attributes of this node and its descendants, including the
+    // contents of templates, the same way as `serializer::RenderOpts::rewrite_url` does while
+    // serializing.
+    pub fn rewrite_urls<F: Fn(&str) -> String>(&self, rewrite: F) {
+        rewrite_urls(self, &rewrite);
+    }
+
+    // Sets the text of text, cdata and comment nodes, other nodes get their children replaced by a
+    // single text node.
+    pub fn set_text(&self, text: StrTendril) {
+        let children = match self.borrow_mut().0 {
+            Node::CData(ref mut old_text) |
+            Node::Comment(ref mut old_text) |
+            Node::Text(ref mut old_text) => {
+                *old_text = text;
+                return;
+            }
+            Node::Document(ref mut children) |
+            Node::Element(_, _, ref mut children) |
+            Node::TemplateContents(ref mut children) => mem::replace(children, Vec::new()),
+            _ => panic!("expected text, cdata, comment, document or element, got: {:?}", self),
+        };
+
+        for child in children {
+            child.borrow_mut().1 = None;
+        }
+
+        if !text.is_empty() {
+            self.append_child(Node::Text(text).into());
+        }
+    }
+}
+
+fn rewrite_urls(handle: &Handle, rewrite: &Fn(&str) -> String) {
+    for node in inclusive_descendants(&[handle.clone()]) {
+        if let Node::Element(_, ref mut attributes, _) = node.borrow_mut().0 {
+            for attribute in attributes.iter_mut() {
+                if let Some(value) = url::rewrite_attribute(&attribute.0, &attribute.1, rewrite) {
+                    attribute.1 = value.into();
+                }
+            }
+        }
+
+        if let Some(contents) = node.template_contents() {
+            rewrite_urls(&contents, rewrite);
+        }
+    }
+}
+
+fn with_children<F, R>(handle: &Handle, f: F) -> R
+    where F: FnOnce(&mut Vec<Handle>) -> R
+{
+    match handle.borrow_mut().0 {
+        Node::Document(ref mut children) |
+        Node::Element(_, _, ref mut children) |
+        Node::TemplateContents(ref mut children) => f(children),
+        _ => panic!("expected document or element, got: {:?}", handle),
+    }
+}
+
+fn check_insertion(parent: &Handle, child: &Handle) {
+    if parent == child || parent.ancestors().any(|a| a == *child) {
+        panic!("cannot insert a node into itself or one of its descendants");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dom::{Handle, Node};
+
+    fn element(name: &str) -> Handle {
+        Node::Element(qualname!(html, "div"), vec![(qualname!("", "id"), name.into())], Vec::new())
+            .into()
+    }
+
+    fn name(handle: &Handle) -> String {
+        match handle.borrow().0 {
+            Node::Element(_, ref attributes, _) => attributes[0].1.to_string(),
+            Node::Text(ref text) => text.to_string(),
+            _ => panic!("expected element or text, got: {:?}", handle),
+        }
+    }
+
+    fn children(handle: &Handle) -> Vec<String> {
+        handle.children().map(|c| name(&c)).collect()
+    }
+
+    fn parent(handle: &Handle) -> Option<String> {
+        handle.parent().map(|p| name(&p))
+    }
+
+    #[test]
+    fn test_append_child() {
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+
+        a.append_child(b.clone());
+        a.append_child(c.clone());
+        assert_eq!(children(&a), &["b", "c"]);
+        assert_eq!(parent(&b), Some("a".to_owned()));
+
+        c.append_child(b.clone());
+        assert_eq!(children(&a), &["c"]);
+        assert_eq!(children(&c), &["b"]);
+        assert_eq!(parent(&b), Some("c".to_owned()));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot insert a node into itself or one of its descendants")]
+    fn test_append_ancestor() {
+        let a = element("a");
+        let b = element("b");
+
+        a.append_child(b.clone());
+        b.append_child(a);
+    }
+
+    #[test]
+    fn test_insert_before() {
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+
+        a.append_child(c.clone());
+        c.insert_before(b.clone());
+        assert_eq!(children(&a), &["b", "c"]);
+        assert_eq!(parent(&b), Some("a".to_owned()));
+
+        b.insert_before(c.clone());
+        assert_eq!(children(&a), &["c", "b"]);
+    }
+
+    #[test]
+    fn test_replace_with() {
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+
+        a.append_child(b.clone());
+        b.replace_with(c.clone());
+        assert_eq!(children(&a), &["c"]);
+        assert_eq!(parent(&b), None);
+        assert_eq!(parent(&c), Some("a".to_owned()));
+    }
+
+    #[test]
+    fn test_detach() {
+        let a = element("a");
+        let b = element("b");
+
+        a.append_child(b.clone());
+        b.detach();
+        assert!(children(&a).is_empty());
+        assert_eq!(parent(&b), None);
+
+        b.detach();
+        assert_eq!(parent(&b), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let a = element("a");
+        let b = element("b");
+        let c = element("c");
+
+        a.append_child(b.clone());
+        b.append_child(c.clone());
+        b.clone().remove();
+        assert!(children(&a).is_empty());
+        assert!(children(&b).is_empty());
+        assert_eq!(parent(&c), None);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let a = element("a");
+
+        a.set_attribute(qualname!("", "class"), "x".into());
+        a.set_attribute(qualname!("", "id"), "b".into());
+        assert_eq!(name(&a), "b");
+
+        match a.borrow().0 {
+            Node::Element(_, ref attributes, _) => assert_eq!(attributes.len(), 2),
+            _ => unreachable!(),
+        }
+
+        assert_eq!(a.remove_attribute(&qualname!("", "class")), Some("x".into()));
+        assert_eq!(a.remove_attribute(&qualname!("", "class")), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let a = element("a");
+        let b = element("b");
+        let text = |s: &str| -> Handle { Node::Text(s.into()).into() };
+
+        a.append_child(text("x"));
+        a.append_child(text(""));
+        a.append_child(text("y"));
+        a.append_child(b.clone());
+        a.append_child(text("z"));
+        b.append_child(text(""));
+
+        a.normalize();
+        assert_eq!(children(&a), &["xy", "b", "z"]);
+        assert!(children(&b).is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_urls() {
+        let a = element("a");
+        let b = element("b");
+
+        a.set_attribute(qualname!("", "href"), "/a".into());
+        b.set_attribute(qualname!("", "style"), "background: url(/b.png)".into());
+        a.append_child(b.clone());
+        a.rewrite_urls(|url| format!("https://cdn.example.com{}", url));
+
+        assert_eq!(a.borrow().0,
+                   Node::Element(qualname!(html, "div"),
+                                 vec![(qualname!("", "id"), "a".into()),
+                                      (qualname!("", "href"), "https://cdn.example.com/a".into())],
+                                 vec![b.clone()]));
+
+        match b.borrow().0 {
+            Node::Element(_, ref attributes, _) => {
+                assert_eq!(&attributes[1].1[..], "background: url(https://cdn.example.com/b.png)")
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_set_text() {
+        let a = element("a");
+        let b = element("b");
+        let text: Handle = Node::Text("x".into()).into();
+
+        a.append_child(b.clone());
+        a.append_child(text.clone());
+        a.set_text("y".into());
+        assert_eq!(children(&a), &["y"]);
+        assert_eq!(parent(&b), None);
+        assert_eq!(parent(&text), None);
+
+        text.set_text("z".into());
+        assert_eq!(name(&text), "z");
+
+        a.set_text("".into());
+        assert!(children(&a).is_empty());
+    }
+}
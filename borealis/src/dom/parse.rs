@@ -0,0 +1,119 @@
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+
+use html5ever::ParseOpts;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tendril::fmt::UTF8;
+use html5ever::tokenizer::TokenizerOpts;
+use html5ever::tree_builder::{QuirksMode, TreeBuilderOpts};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: Cow<'static, str>,
+    pub position: Option<Position>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{}:{}: {}", position.line, position.column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Parsed<T> {
+    pub output: T,
+    pub errors: Vec<ParseError>,
+    pub quirks_mode: QuirksMode,
+}
+
+impl<T> Parsed<T> {
+    pub fn into_result(self) -> Result<T, Vec<ParseError>> {
+        if self.errors.is_empty() {
+            Ok(self.output)
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+pub fn exact_errors_opts() -> ParseOpts {
+    ParseOpts {
+        tokenizer: TokenizerOpts { exact_errors: true, ..Default::default() },
+        tree_builder: TreeBuilderOpts { exact_errors: true, ..Default::default() },
+    }
+}
+
+// Feeds `input` to `sink` in chunks split before every `<` and after every `>`, so that
+// the tokens produced while processing a chunk start at, or very close to, the start of
+// the chunk. `position` is set to the start of each chunk before it is processed.
+pub fn feed_with_positions<S>(mut sink: S,
+                              position: &Cell<Option<Position>>,
+                              input: &str)
+                              -> S::Output
+    where S: TendrilSink<UTF8>
+{
+    fn process<S>(sink: &mut S,
+                  position: &Cell<Option<Position>>,
+                  input: &str,
+                  start: Position,
+                  end: usize)
+        where S: TendrilSink<UTF8>
+    {
+        if start.offset != end {
+            position.set(Some(start));
+            sink.process(StrTendril::from_slice(&input[start.offset..end]));
+        }
+    }
+
+    let mut current = Position {
+        line: 1,
+        column: 1,
+        offset: 0,
+    };
+    let mut chunk_start = current;
+
+    for (offset, c) in input.char_indices() {
+        if c == '<' {
+            process(&mut sink, position, input, chunk_start, offset);
+            chunk_start = current;
+        }
+
+        current.offset = offset + c.len_utf8();
+
+        if c == '\n' {
+            current.line += 1;
+            current.column = 1;
+        } else {
+            current.column += 1;
+        }
+
+        if c == '>' {
+            process(&mut sink, position, input, chunk_start, current.offset);
+            chunk_start = current;
+        }
+    }
+
+    process(&mut sink, position, input, chunk_start, input.len());
+
+    position.set(Some(current));
+    sink.finish()
+}
@@ -0,0 +1,343 @@
+
+use std::collections::{HashMap, HashSet};
+
+use html5ever::tendril::StrTendril;
+
+use string_cache::QualName;
+
+use url::URL_ATTRIBUTES;
+use super::{Handle, Node};
+
+/// An allowlist of what is kept when sanitizing untrusted HTML. Elements that aren't allowed are
+/// replaced by their sanitized children, except for those with cleaned content, like scripts,
+/// which are dropped along with their children. Comments and other markup are always dropped.
+///
+/// Style attributes aren't sanitized, they shouldn't be allowed for untrusted input.
+#[derive(Clone, Debug)]
+pub struct SanitizePolicy {
+    elements: HashSet<String>,
+    attributes: HashMap<String, HashSet<String>>,
+    generic_attributes: HashSet<String>,
+    url_schemes: HashSet<String>,
+    classes: Option<HashSet<String>>,
+    clean_content: HashSet<String>,
+    add_noopener: bool,
+}
+
+impl SanitizePolicy {
+    /// Keeps only text. URLs may use the http, https and mailto schemes once their attributes are
+    /// allowed.
+    pub fn new() -> SanitizePolicy {
+        SanitizePolicy::default()
+    }
+
+    /// Basic formatting, links, images and lists, suitable for user comments.
+    pub fn basic() -> SanitizePolicy {
+        SanitizePolicy::new()
+            .elements(&["a", "abbr", "b", "blockquote", "br", "code", "em", "i", "img", "li",
+                        "ol", "p", "pre", "q", "s", "strong", "sub", "sup", "u", "ul"])
+            .attributes("a", &["href", "title"])
+            .attributes("abbr", &["title"])
+            .attributes("blockquote", &["cite"])
+            .attributes("img", &["alt", "height", "src", "title", "width"])
+            .attributes("q", &["cite"])
+            .add_noopener(true)
+    }
+
+    /// Allows the elements, in the HTML namespace and by their local name.
+    pub fn elements(mut self, elements: &[&str]) -> SanitizePolicy {
+        self.elements.extend(elements.iter().map(|e| e.to_string()));
+        self
+    }
+
+    /// Allows the attributes on `element`. URL attributes are still dropped unless their scheme
+    /// is allowed.
+    pub fn attributes(mut self, element: &str, attributes: &[&str]) -> SanitizePolicy {
+        self.attributes
+            .entry(element.to_owned())
+            .or_insert_with(HashSet::new)
+            .extend(attributes.iter().map(|a| a.to_string()));
+        self
+    }
+
+    /// Allows the attributes on every element.
+    pub fn generic_attributes(mut self, attributes: &[&str]) -> SanitizePolicy {
+        self.generic_attributes.extend(attributes.iter().map(|a| a.to_string()));
+        self
+    }
+
+    /// Replaces the allowed schemes, relative URLs are always allowed.
+    pub fn url_schemes(mut self, schemes: &[&str]) -> SanitizePolicy {
+        self.url_schemes = schemes.iter().map(|s| s.to_lowercase()).collect();
+        self
+    }
+
+    /// Allows the class attribute on every element, with only the given classes kept.
+    pub fn classes(mut self, classes: &[&str]) -> SanitizePolicy {
+        let mut allowed = self.classes.take().unwrap_or_else(HashSet::new);
+        allowed.extend(classes.iter().map(|c| c.to_string()));
+        self.classes = Some(allowed);
+        self
+    }
+
+    /// Drops the elements along with their children instead of keeping their children.
+    pub fn clean_content(mut self, elements: &[&str]) -> SanitizePolicy {
+        self.clean_content.extend(elements.iter().map(|e| e.to_string()));
+        self
+    }
+
+    /// Adds `rel="noopener"` to links, so that opened pages can't reach back through
+    /// `window.opener`.
+    pub fn add_noopener(mut self, add_noopener: bool) -> SanitizePolicy {
+        self.add_noopener = add_noopener;
+        self
+    }
+
+    fn allows_attribute(&self, element: &str, attribute: &str) -> bool {
+        self.generic_attributes.contains(attribute) ||
+        (attribute == "class" && self.classes.is_some()) ||
+        self.attributes.get(element).map_or(false, |a| a.contains(attribute))
+    }
+
+    fn allows_url(&self, url: &str) -> bool {
+        // Browsers ignore whitespace and control characters in schemes, as in "java\tscript:".
+        let url: String = url.chars().filter(|c| !c.is_whitespace() && !c.is_control()).collect();
+
+        match url.find(|c: char| c == ':' || c == '/' || c == '?' || c == '#') {
+            Some(i) if url[i..].starts_with(':') => {
+                self.url_schemes.contains(&url[..i].to_lowercase())
+            }
+            _ => true,
+        }
+    }
+
+    fn clean_attributes(&self,
+                        element: &str,
+                        attributes: &[(QualName, StrTendril)])
+                        -> Vec<(QualName, StrTendril)> {
+        let mut clean = Vec::new();
+
+        for &(ref name, ref value) in attributes.iter() {
+            let local = &*name.local;
+
+            if name.ns != ns!("") || !self.allows_attribute(element, local) {
+                continue;
+            }
+
+            let allowed = match local {
+                "srcset" => {
+                    value.split(',')
+                         .all(|c| self.allows_url(c.split_whitespace().next().unwrap_or("")))
+                }
+                _ if URL_ATTRIBUTES.contains(&local) => self.allows_url(value),
+                _ => true,
+            };
+
+            if !allowed {
+                continue;
+            }
+
+            let value = match (local, &self.classes) {
+                ("class", &Some(ref classes)) => {
+                    let value = value.split_whitespace()
+                                     .filter(|c| classes.contains(*c))
+                                     .collect::<Vec<_>>()
+                                     .join(" ");
+
+                    if value.is_empty() {
+                        continue;
+                    }
+
+                    value.into()
+                }
+                _ => value.clone(),
+            };
+
+            clean.push((name.clone(), value));
+        }
+
+        if self.add_noopener && (element == "a" || element == "area") &&
+           clean.iter().any(|a| &*a.0.local == "href") {
+            add_noopener(&mut clean);
+        }
+
+        clean
+    }
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> SanitizePolicy {
+        SanitizePolicy {
+            elements: HashSet::new(),
+            attributes: HashMap::new(),
+            generic_attributes: HashSet::new(),
+            url_schemes: ["http", "https", "mailto"].iter().map(|s| s.to_string()).collect(),
+            classes: None,
+            clean_content: ["noscript", "script", "style", "template"]
+                               .iter()
+                               .map(|e| e.to_string())
+                               .collect(),
+            add_noopener: false,
+        }
+    }
+}
+
+fn add_noopener(attributes: &mut Vec<(QualName, StrTendril)>) {
+    match attributes.iter().position(|a| &*a.0.local == "rel") {
+        Some(i) => {
+            let has_noopener = attributes[i].1
+                                            .to_lowercase()
+                                            .split_whitespace()
+                                            .any(|r| r == "noopener");
+
+            if !has_noopener {
+                let rel = format!("{} noopener", attributes[i].1.trim());
+                attributes[i].1 = rel.trim().into();
+            }
+        }
+        None => attributes.push((qualname!("", "rel"), "noopener".into())),
+    }
+}
+
+/// Builds sanitized copies of the nodes, leaving them untouched.
+pub fn sanitize(nodes: &[Handle], policy: &SanitizePolicy) -> Vec<Handle> {
+    let mut clean = Vec::new();
+
+    for node in nodes.iter() {
+        sanitize_node(node, policy, &mut clean);
+    }
+
+    clean
+}
+
+fn sanitize_node(node: &Handle, policy: &SanitizePolicy, clean: &mut Vec<Handle>) {
+    match node.borrow().0 {
+        Node::Text(ref text) => push_text(clean, text),
+        Node::Element(ref name, ref attributes, ref children) => {
+            let local = &*name.local;
+
+            if name.ns == ns!(html) && policy.elements.contains(local) {
+                let attributes = policy.clean_attributes(local, attributes);
+                let element: Handle = Node::Element(name.clone(), attributes, Vec::new()).into();
+
+                for child in sanitize(children, policy) {
+                    element.append_child(child);
+                }
+
+                if let (Some(contents), Some(clean_contents)) = (node.template_contents(),
+                                                                 element.template_contents()) {
+                    for child in sanitize(&contents.children().collect::<Vec<_>>(), policy) {
+                        clean_contents.append_child(child);
+                    }
+                }
+
+                clean.push(element);
+            } else if !policy.clean_content.contains(local) {
+                for child in children.iter() {
+                    sanitize_node(child, policy, clean);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Merges text with the previous node, since unwrapped elements can leave text nodes next to
+// each other.
+fn push_text(clean: &mut Vec<Handle>, text: &StrTendril) {
+    if let Some(last) = clean.last() {
+        if let Node::Text(ref mut last_text) = last.borrow_mut().0 {
+            last_text.push_tendril(text);
+            return;
+        }
+    }
+
+    clean.push(Node::Text(text.clone()).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use dom::Fragment;
+    use serializer::{self, SerializeDocument, SerializeNode, DocumentSerializer};
+
+    struct Doc(Fragment);
+
+    impl SerializeDocument for Doc {
+        fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+            let mut s = s.node();
+            self.0.serialize_node(&mut s);
+        }
+    }
+
+    fn sanitize(s: &str, policy: &SanitizePolicy) -> String {
+        serializer::to_string(Doc(Fragment::parse_str(s).sanitize(policy))).unwrap()
+    }
+
+    #[test]
+    fn test_elements() {
+        let policy = SanitizePolicy::basic();
+
+        assert_eq!(sanitize("<p>a <b>b</b> <span>c <i>d</i></span></p>", &policy),
+                   "<p>a <b>b</b> c <i>d</i></p>");
+        assert_eq!(sanitize("a<script>alert(1)</script><style>p {}</style><!--b-->c", &policy),
+                   "ac");
+        assert_eq!(sanitize("<svg><a href=\"/a\">a</a></svg>", &policy), "a");
+        assert_eq!(sanitize("<p>a</p>", &SanitizePolicy::new()), "a");
+
+        let policy = SanitizePolicy::basic().elements(&["template"]);
+        assert_eq!(sanitize("<template><p>a<script>b</script></p></template>", &policy),
+                   "<template><p>a</p></template>");
+    }
+
+    #[test]
+    fn test_attributes() {
+        let policy = SanitizePolicy::basic().generic_attributes(&["title"]);
+
+        assert_eq!(sanitize("<p id=\"a\" title=\"b\" onclick=\"c\">d</p>", &policy),
+                   "<p title=\"b\">d</p>");
+        assert_eq!(sanitize("<img src=\"/a.png\" alt=\"a\" style=\"b\">", &policy),
+                   "<img src=\"/a.png\" alt=\"a\">");
+    }
+
+    #[test]
+    fn test_urls() {
+        let policy = SanitizePolicy::basic().add_noopener(false);
+
+        assert_eq!(sanitize("<a href=\"https://a.com/\">a</a>", &policy),
+                   "<a href=\"https://a.com/\">a</a>");
+        assert_eq!(sanitize("<a href=\"/a:b\">a</a><a href=\"?a:b\">b</a>", &policy),
+                   "<a href=\"/a:b\">a</a><a href=\"?a:b\">b</a>");
+        assert_eq!(sanitize("<a href=\"javascript:alert(1)\">a</a>", &policy), "<a>a</a>");
+        assert_eq!(sanitize("<a href=\" JaVa&#9;Script:alert(1)\">a</a>", &policy), "<a>a</a>");
+        assert_eq!(sanitize("<img src=\"data:image/png,a\">", &policy), "<img>");
+
+        let policy = policy.url_schemes(&["data"]);
+        assert_eq!(sanitize("<img src=\"data:image/png,a\">", &policy),
+                   "<img src=\"data:image/png,a\">");
+    }
+
+    #[test]
+    fn test_noopener() {
+        let policy = SanitizePolicy::basic().attributes("a", &["rel"]);
+
+        assert_eq!(sanitize("<a href=\"/a\">a</a>", &policy),
+                   "<a href=\"/a\" rel=\"noopener\">a</a>");
+        assert_eq!(sanitize("<a href=\"/a\" rel=\"nofollow\">a</a>", &policy),
+                   "<a href=\"/a\" rel=\"nofollow noopener\">a</a>");
+        assert_eq!(sanitize("<a href=\"/a\" rel=\"NoOpener\">a</a>", &policy),
+                   "<a href=\"/a\" rel=\"NoOpener\">a</a>");
+        assert_eq!(sanitize("<a name=\"a\">a</a>", &policy), "<a>a</a>");
+    }
+
+    #[test]
+    fn test_classes() {
+        let policy = SanitizePolicy::basic().classes(&["a", "b"]);
+
+        assert_eq!(sanitize("<p class=\"a c b\">a</p><p class=\"c\">b</p>", &policy),
+                   "<p class=\"a b\">a</p><p>b</p>");
+        assert_eq!(sanitize("<p class=\"a\">a</p>", &SanitizePolicy::basic()), "<p>a</p>");
+    }
+}
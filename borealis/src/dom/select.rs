@@ -0,0 +1,826 @@
+
+use std::ascii::AsciiExt;
+use std::error::Error;
+use std::fmt;
+
+use super::{Descendants, Handle, Node};
+use super::iter::inclusive_descendants;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectorError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for SelectorError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+// A list of selectors separated by commas, matching an element if any of them matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selectors(Vec<Selector>);
+
+impl Selectors {
+    pub fn parse(s: &str) -> Result<Selectors, SelectorError> {
+        Parser::new(s).parse_selectors()
+    }
+
+    pub fn matches(&self, handle: &Handle) -> bool {
+        self.matches_in_scope(handle, &[])
+    }
+
+    // Like `matches`, but the parents of the nodes in `scope` are left out, so that the synthetic
+    // root of a fragment or its context element can't be matched by a combinator.
+    fn matches_in_scope(&self, handle: &Handle, scope: &[Handle]) -> bool {
+        is_element(handle) && self.0.iter().any(|s| s.matches(handle, scope))
+    }
+}
+
+// Compound selectors and the combinators between them, both from left to right.
+#[derive(Clone, Debug, PartialEq)]
+struct Selector {
+    compounds: Vec<Vec<Simple>>,
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    Adjacent,
+    Sibling,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Simple {
+    Universal,
+    Type(String),
+    Id(String),
+    Class(String),
+    Attribute(String, AttributeOperator, String),
+    Nth(Nth, i32, i32),
+    Empty,
+    Root,
+    Not(Box<Simple>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AttributeOperator {
+    Exists,
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Nth {
+    Child,
+    LastChild,
+    OfType,
+    LastOfType,
+}
+
+impl Selector {
+    fn matches(&self, handle: &Handle, scope: &[Handle]) -> bool {
+        self.matches_compound(self.compounds.len() - 1, handle, scope)
+    }
+
+    fn matches_compound(&self, index: usize, handle: &Handle, scope: &[Handle]) -> bool {
+        if !self.compounds[index].iter().all(|s| s.matches(handle)) {
+            return false;
+        }
+
+        if index == 0 {
+            return true;
+        }
+
+        match self.combinators[index - 1] {
+            Combinator::Descendant => {
+                let mut ancestor = scoped_parent(handle, scope);
+
+                while let Some(a) = ancestor {
+                    if is_element(&a) && self.matches_compound(index - 1, &a, scope) {
+                        return true;
+                    }
+
+                    ancestor = scoped_parent(&a, scope);
+                }
+
+                false
+            }
+            Combinator::Child => {
+                match scoped_parent(handle, scope) {
+                    Some(ref parent) if is_element(parent) => {
+                        self.matches_compound(index - 1, parent, scope)
+                    }
+                    _ => false,
+                }
+            }
+            Combinator::Adjacent => {
+                match handle.preceding_siblings().find(is_element) {
+                    Some(ref sibling) => self.matches_compound(index - 1, sibling, scope),
+                    None => false,
+                }
+            }
+            Combinator::Sibling => {
+                handle.preceding_siblings()
+                      .filter(is_element)
+                      .any(|s| self.matches_compound(index - 1, &s, scope))
+            }
+        }
+    }
+}
+
+impl Simple {
+    fn matches(&self, handle: &Handle) -> bool {
+        if let Simple::Not(ref simple) = *self {
+            return !simple.matches(handle);
+        }
+
+        let node = handle.borrow();
+        let (name, attributes) = match node.0 {
+            Node::Element(ref name, ref attributes, _) => (name, attributes),
+            _ => return false,
+        };
+
+        let attribute = |attribute: &str| {
+            attributes.iter()
+                      .find(|a| a.0.local.eq_ignore_ascii_case(attribute))
+                      .map(|a| &a.1[..])
+        };
+
+        match *self {
+            Simple::Universal => true,
+            Simple::Type(ref local) => name.local.eq_ignore_ascii_case(local),
+            Simple::Id(ref id) => attribute("id") == Some(&id[..]),
+            Simple::Class(ref class) => {
+                attribute("class").map_or(false, |c| contains_word(c, class))
+            }
+            Simple::Attribute(ref attribute_name, operator, ref expected) => {
+                let value = match attribute(attribute_name) {
+                    Some(value) => value,
+                    None => return false,
+                };
+
+                match operator {
+                    AttributeOperator::Exists => true,
+                    AttributeOperator::Equals => value == &expected[..],
+                    AttributeOperator::Includes => {
+                        !expected.is_empty() && contains_word(value, expected)
+                    }
+                    AttributeOperator::DashMatch => {
+                        value == &expected[..] ||
+                        (value.starts_with(&expected[..]) &&
+                         value[expected.len()..].starts_with('-'))
+                    }
+                    AttributeOperator::Prefix => {
+                        !expected.is_empty() && value.starts_with(&expected[..])
+                    }
+                    AttributeOperator::Suffix => {
+                        !expected.is_empty() && value.ends_with(&expected[..])
+                    }
+                    AttributeOperator::Substring => {
+                        !expected.is_empty() && value.contains(&expected[..])
+                    }
+                }
+            }
+            Simple::Nth(nth, a, b) => {
+                let index = match nth {
+                    Nth::Child => handle.preceding_siblings().filter(is_element).count(),
+                    Nth::LastChild => handle.following_siblings().filter(is_element).count(),
+                    Nth::OfType => {
+                        handle.preceding_siblings().filter(|s| has_name(s, &name.local)).count()
+                    }
+                    Nth::LastOfType => {
+                        handle.following_siblings().filter(|s| has_name(s, &name.local)).count()
+                    }
+                };
+
+                matches_nth(a, b, index as i32 + 1)
+            }
+            Simple::Empty => {
+                handle.children().all(|c| {
+                    match c.borrow().0 {
+                        Node::Element(..) => false,
+                        Node::Text(ref text) => text.is_empty(),
+                        _ => true,
+                    }
+                })
+            }
+            Simple::Root => handle.parent().map_or(false, |p| is_document(&p)),
+            Simple::Not(..) => unreachable!(),
+        }
+    }
+}
+
+// Tells if there is an `n >= 0` such that `a * n + b == index`.
+fn matches_nth(a: i32, b: i32, index: i32) -> bool {
+    if a == 0 {
+        index == b
+    } else {
+        let n = index - b;
+        n % a == 0 && n / a >= 0
+    }
+}
+
+fn scoped_parent(handle: &Handle, scope: &[Handle]) -> Option<Handle> {
+    if scope.contains(handle) {
+        None
+    } else {
+        handle.parent()
+    }
+}
+
+fn is_element(handle: &Handle) -> bool {
+    match handle.borrow().0 {
+        Node::Element(..) => true,
+        _ => false,
+    }
+}
+
+fn is_document(handle: &Handle) -> bool {
+    match handle.borrow().0 {
+        Node::Document(..) => true,
+        _ => false,
+    }
+}
+
+fn has_name(handle: &Handle, local: &str) -> bool {
+    match handle.borrow().0 {
+        Node::Element(ref name, _, _) => &*name.local == local,
+        _ => false,
+    }
+}
+
+fn contains_word(s: &str, word: &str) -> bool {
+    s.split(is_whitespace).any(|w| w == word)
+}
+
+fn is_whitespace(c: char) -> bool {
+    match c {
+        ' ' | '\t' | '\n' | '\x0c' | '\r' => true,
+        _ => false,
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Parser<'a> {
+        Parser {
+            input: input,
+            position: 0,
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, SelectorError> {
+        Err(SelectorError {
+            message: message.to_owned(),
+            position: self.position,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+
+        if let Some(c) = c {
+            self.position += c.len_utf8();
+        }
+
+        c
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SelectorError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", expected))
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+
+        while self.peek().map_or(false, is_whitespace) {
+            self.next();
+        }
+
+        self.position != start
+    }
+
+    fn parse_selectors(&mut self) -> Result<Selectors, SelectorError> {
+        let mut selectors = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            selectors.push(try!(self.parse_selector()));
+
+            match self.next() {
+                Some(',') => continue,
+                Some(_) => return self.error("unexpected character"),
+                None => return Ok(Selectors(selectors)),
+            }
+        }
+    }
+
+    fn parse_selector(&mut self) -> Result<Selector, SelectorError> {
+        let mut compounds = vec![try!(self.parse_compound())];
+        let mut combinators = Vec::new();
+
+        loop {
+            let whitespace = self.skip_whitespace();
+
+            let combinator = match self.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::Adjacent,
+                Some('~') => Combinator::Sibling,
+                Some(',') | None => break,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(_) => return self.error("unexpected character"),
+            };
+
+            if combinator != Combinator::Descendant {
+                self.next();
+                self.skip_whitespace();
+            }
+
+            combinators.push(combinator);
+            compounds.push(try!(self.parse_compound()));
+        }
+
+        Ok(Selector {
+            compounds: compounds,
+            combinators: combinators,
+        })
+    }
+
+    fn parse_compound(&mut self) -> Result<Vec<Simple>, SelectorError> {
+        let mut compound = Vec::new();
+
+        if self.eat('*') {
+            compound.push(Simple::Universal);
+        } else if self.peek().map_or(false, is_identifier_start) {
+            compound.push(Simple::Type(try!(self.parse_identifier()).to_ascii_lowercase()));
+        }
+
+        loop {
+            match self.peek() {
+                Some(':') => {
+                    self.next();
+                    try!(self.parse_pseudo_class(&mut compound));
+                }
+                Some(_) => {
+                    match try!(self.parse_simple()) {
+                        Some(simple) => compound.push(simple),
+                        None => break,
+                    }
+                }
+                None => break,
+            }
+        }
+
+        if compound.is_empty() {
+            self.error("expected selector")
+        } else {
+            Ok(compound)
+        }
+    }
+
+    // Parses an id, class or attribute selector.
+    fn parse_simple(&mut self) -> Result<Option<Simple>, SelectorError> {
+        match self.peek() {
+            Some('#') => {
+                self.next();
+                Ok(Some(Simple::Id(try!(self.parse_identifier()))))
+            }
+            Some('.') => {
+                self.next();
+                Ok(Some(Simple::Class(try!(self.parse_identifier()))))
+            }
+            Some('[') => {
+                self.next();
+                self.parse_attribute().map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_attribute(&mut self) -> Result<Simple, SelectorError> {
+        self.skip_whitespace();
+        let name = try!(self.parse_identifier()).to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let operator = match self.next() {
+            Some(']') => {
+                return Ok(Simple::Attribute(name, AttributeOperator::Exists, String::new()));
+            }
+            Some('=') => AttributeOperator::Equals,
+            Some(c) => {
+                let operator = match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    _ => return self.error("expected attribute operator or `]`"),
+                };
+
+                try!(self.expect('='));
+                operator
+            }
+            None => return self.error("expected attribute operator or `]`"),
+        };
+
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some(quote @ '"') | Some(quote @ '\'') => {
+                self.next();
+                try!(self.parse_string(quote))
+            }
+            _ => try!(self.parse_identifier()),
+        };
+
+        self.skip_whitespace();
+        try!(self.expect(']'));
+
+        Ok(Simple::Attribute(name, operator, value))
+    }
+
+    fn parse_pseudo_class(&mut self, compound: &mut Vec<Simple>) -> Result<(), SelectorError> {
+        if self.peek() == Some(':') {
+            return self.error("pseudo-elements are not supported");
+        }
+
+        let name = try!(self.parse_identifier()).to_ascii_lowercase();
+
+        match &name[..] {
+            "first-child" => compound.push(Simple::Nth(Nth::Child, 0, 1)),
+            "last-child" => compound.push(Simple::Nth(Nth::LastChild, 0, 1)),
+            "only-child" => {
+                compound.push(Simple::Nth(Nth::Child, 0, 1));
+                compound.push(Simple::Nth(Nth::LastChild, 0, 1));
+            }
+            "first-of-type" => compound.push(Simple::Nth(Nth::OfType, 0, 1)),
+            "last-of-type" => compound.push(Simple::Nth(Nth::LastOfType, 0, 1)),
+            "only-of-type" => {
+                compound.push(Simple::Nth(Nth::OfType, 0, 1));
+                compound.push(Simple::Nth(Nth::LastOfType, 0, 1));
+            }
+            "empty" => compound.push(Simple::Empty),
+            "root" => compound.push(Simple::Root),
+            "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type" => {
+                let nth = match &name[..] {
+                    "nth-child" => Nth::Child,
+                    "nth-last-child" => Nth::LastChild,
+                    "nth-of-type" => Nth::OfType,
+                    _ => Nth::LastOfType,
+                };
+
+                try!(self.expect('('));
+                let (a, b) = try!(self.parse_nth());
+                try!(self.expect(')'));
+
+                compound.push(Simple::Nth(nth, a, b));
+            }
+            "not" => {
+                try!(self.expect('('));
+                self.skip_whitespace();
+
+                let simple = if self.eat('*') {
+                    Simple::Universal
+                } else if self.peek().map_or(false, is_identifier_start) {
+                    Simple::Type(try!(self.parse_identifier()).to_ascii_lowercase())
+                } else if self.eat(':') {
+                    let mut simples = Vec::new();
+                    try!(self.parse_pseudo_class(&mut simples));
+
+                    if simples.len() != 1 {
+                        return self.error("expected a simple selector in `:not()`");
+                    }
+
+                    simples.pop().unwrap()
+                } else {
+                    match try!(self.parse_simple()) {
+                        Some(simple) => simple,
+                        None => return self.error("expected a simple selector in `:not()`"),
+                    }
+                };
+
+                self.skip_whitespace();
+                try!(self.expect(')'));
+
+                compound.push(Simple::Not(Box::new(simple)));
+            }
+            _ => return self.error(&format!("unsupported pseudo-class `:{}`", name)),
+        }
+
+        Ok(())
+    }
+
+    // Parses the `an+b` argument of the `:nth-*` pseudo-classes.
+    fn parse_nth(&mut self) -> Result<(i32, i32), SelectorError> {
+        let start = self.position;
+        let end = match self.input[start..].find(')') {
+            Some(end) => start + end,
+            None => return self.error("expected `)`"),
+        };
+
+        let argument = self.input[start..end]
+                           .chars()
+                           .filter(|&c| !is_whitespace(c))
+                           .collect::<String>()
+                           .to_ascii_lowercase();
+
+        let parse_int = |s: &str| {
+            let s = if s.starts_with('+') { &s[1..] } else { s };
+            s.parse::<i32>().ok()
+        };
+
+        let nth = match &argument[..] {
+            "odd" => Some((2, 1)),
+            "even" => Some((2, 0)),
+            argument => {
+                match argument.find('n') {
+                    Some(n) => {
+                        let a = match &argument[..n] {
+                            "" | "+" => Some(1),
+                            "-" => Some(-1),
+                            a => parse_int(a),
+                        };
+                        let b = match &argument[n + 1..] {
+                            "" => Some(0),
+                            b if b.starts_with('+') || b.starts_with('-') => parse_int(b),
+                            _ => None,
+                        };
+
+                        match (a, b) {
+                            (Some(a), Some(b)) => Some((a, b)),
+                            _ => None,
+                        }
+                    }
+                    None => parse_int(argument).map(|b| (0, b)),
+                }
+            }
+        };
+
+        match nth {
+            Some(nth) => {
+                self.position = end;
+                Ok(nth)
+            }
+            None => self.error("invalid `an+b` expression"),
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, SelectorError> {
+        let mut identifier = String::new();
+
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.next();
+
+                    match self.next() {
+                        Some(c) => identifier.push(c),
+                        None => return self.error("unexpected end of selector after `\\`"),
+                    }
+                }
+                Some(c) if is_identifier_start(c) || c.is_digit(10) || c == '-' => {
+                    self.next();
+                    identifier.push(c);
+                }
+                _ => break,
+            }
+        }
+
+        if identifier.is_empty() {
+            self.error("expected identifier")
+        } else {
+            Ok(identifier)
+        }
+    }
+
+    fn parse_string(&mut self, quote: char) -> Result<String, SelectorError> {
+        let mut string = String::new();
+
+        loop {
+            match self.next() {
+                Some('\\') => {
+                    match self.next() {
+                        Some(c) => string.push(c),
+                        None => return self.error("unterminated string"),
+                    }
+                }
+                Some(c) if c == quote => return Ok(string),
+                Some(c) => string.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '-' || c == '\\' || !c.is_ascii()
+}
+
+pub struct Select {
+    nodes: Descendants,
+    selectors: Selectors,
+    scope: Vec<Handle>,
+}
+
+impl Iterator for Select {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        let selectors = &self.selectors;
+        let scope = &self.scope;
+        self.nodes.find(|n| selectors.matches_in_scope(n, scope))
+    }
+}
+
+// Selects the matching nodes among `roots` and their descendants. The roots are the top of the
+// tree, combinators don't look at their parents.
+pub fn select(roots: &[Handle], selectors: &str) -> Result<Select, SelectorError> {
+    let selectors = try!(Selectors::parse(selectors));
+
+    Ok(Select {
+        nodes: inclusive_descendants(roots),
+        selectors: selectors,
+        scope: roots.to_vec(),
+    })
+}
+
+impl Handle {
+    // Unlike a fragment, the ancestors of the node can be matched by combinators.
+    pub fn select(&self, selectors: &str) -> Result<Select, SelectorError> {
+        let children = self.children().collect::<Vec<_>>();
+        let selectors = try!(Selectors::parse(selectors));
+
+        Ok(Select {
+            nodes: inclusive_descendants(&children),
+            selectors: selectors,
+            scope: Vec::new(),
+        })
+    }
+
+    pub fn select_first(&self, selectors: &str) -> Result<Option<Handle>, SelectorError> {
+        self.select(selectors).map(|mut s| s.next())
+    }
+
+    pub fn matches(&self, selectors: &str) -> Result<bool, SelectorError> {
+        Selectors::parse(selectors).map(|s| s.matches(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::{Document, Fragment, Handle, Node};
+
+    fn ids<I: Iterator<Item = Handle>>(iter: I) -> Vec<String> {
+        iter.map(|h| {
+                match h.borrow().0 {
+                    Node::Element(_, ref attributes, _) => {
+                        attributes.iter()
+                                  .find(|a| &*a.0.local == "id")
+                                  .map_or(String::new(), |a| a.1.to_string())
+                    }
+                    _ => panic!("expected element, got: {:?}", h),
+                }
+            })
+            .collect()
+    }
+
+    fn select_ids(html: &str, selectors: &str) -> Vec<String> {
+        ids(Fragment::parse_str(html).select(selectors).unwrap())
+    }
+
+    const LIST: &'static str = "<ul id=\"list\" class=\"menu main\">\
+                                <li id=\"a\" class=\"item\" lang=\"en-US\">A</li>\
+                                <li id=\"b\" class=\"item active\" data-x=\"foo-bar\"></li>\
+                                <li id=\"c\" class=\"item\"><span id=\"d\">D</span></li>\
+                                </ul><p id=\"e\"></p>";
+
+    #[test]
+    fn test_select_type_class_id() {
+        assert_eq!(select_ids(LIST, "li"), &["a", "b", "c"]);
+        assert_eq!(select_ids(LIST, "LI"), &["a", "b", "c"]);
+        assert_eq!(select_ids(LIST, ".active"), &["b"]);
+        assert_eq!(select_ids(LIST, "ul.menu.main"), &["list"]);
+        assert_eq!(select_ids(LIST, "#c"), &["c"]);
+        assert_eq!(select_ids(LIST, "*"), &["list", "a", "b", "c", "d", "e"]);
+        assert_eq!(select_ids(LIST, "span, #e"), &["d", "e"]);
+    }
+
+    #[test]
+    fn test_select_attributes() {
+        assert_eq!(select_ids(LIST, "[data-x]"), &["b"]);
+        assert_eq!(select_ids(LIST, "[class=item]"), &["a", "c"]);
+        assert_eq!(select_ids(LIST, "[class~=\"active\"]"), &["b"]);
+        assert_eq!(select_ids(LIST, "[lang|=en]"), &["a"]);
+        assert_eq!(select_ids(LIST, "[data-x^=foo]"), &["b"]);
+        assert_eq!(select_ids(LIST, "[data-x$='bar']"), &["b"]);
+        assert_eq!(select_ids(LIST, "[data-x*=o-b]"), &["b"]);
+        assert_eq!(select_ids(LIST, "[id^='']"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_select_combinators() {
+        assert_eq!(select_ids(LIST, "ul span"), &["d"]);
+        assert_eq!(select_ids(LIST, "ul > span"), Vec::<String>::new());
+        assert_eq!(select_ids(LIST, "ul>li>span"), &["d"]);
+        assert_eq!(select_ids(LIST, "#a + li"), &["b"]);
+        assert_eq!(select_ids(LIST, "#a ~ li"), &["b", "c"]);
+        assert_eq!(select_ids(LIST, "ul ~ p"), &["e"]);
+    }
+
+    #[test]
+    fn test_select_in_fragment() {
+        assert_eq!(select_ids("<p id=\"a\"></p>", "html > p"), Vec::<String>::new());
+        assert_eq!(select_ids("<p id=\"a\"></p>", "html p"), Vec::<String>::new());
+        assert_eq!(select_ids("<p id=\"a\"></p>", ":root > p"), Vec::<String>::new());
+
+        let rows = "<tr id=\"a\"><td id=\"b\"></td></tr>";
+        let fragment = Fragment::parse_str_in_context(rows, qualname!(html, "tbody"));
+
+        assert_eq!(ids(fragment.select("tbody > tr").unwrap()), Vec::<String>::new());
+        assert_eq!(ids(fragment.select("tr > td").unwrap()), &["b"]);
+    }
+
+    #[test]
+    fn test_select_pseudo_classes() {
+        assert_eq!(select_ids(LIST, "li:first-child"), &["a"]);
+        assert_eq!(select_ids(LIST, "li:last-child"), &["c"]);
+        assert_eq!(select_ids(LIST, ":only-child"), &["d"]);
+        assert_eq!(select_ids(LIST, "li:nth-child(2)"), &["b"]);
+        assert_eq!(select_ids(LIST, "li:nth-child(odd)"), &["a", "c"]);
+        assert_eq!(select_ids(LIST, "li:nth-child(2n)"), &["b"]);
+        assert_eq!(select_ids(LIST, "li:nth-child(-n + 2)"), &["a", "b"]);
+        assert_eq!(select_ids(LIST, "li:nth-last-child(1)"), &["c"]);
+        assert_eq!(select_ids(LIST, "li:not(.active)"), &["a", "c"]);
+        assert_eq!(select_ids(LIST, "li:not(:first-child)"), &["b", "c"]);
+        assert_eq!(select_ids(LIST, "li:empty"), &["b"]);
+    }
+
+    #[test]
+    fn test_select_document() {
+        let document = Document::parse_str("<!DOCTYPE html><title id=\"t\">Title</title>");
+
+        assert_eq!(ids(document.select(":root").unwrap()), &[""]);
+        assert_eq!(ids(document.select("head > title").unwrap()), &["t"]);
+        assert!(document.select_first("title").unwrap().is_some());
+        assert!(document.select_first("p").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_matches() {
+        let fragment = Fragment::parse_str(LIST);
+        let b = fragment.select_first("#b").unwrap().unwrap();
+
+        assert_eq!(b.matches("ul > .item"), Ok(true));
+        assert_eq!(b.matches("p"), Ok(false));
+    }
+
+    #[test]
+    fn test_selector_errors() {
+        let error = |s: &str| Selectors::parse(s).unwrap_err();
+
+        assert_eq!(error("").position, 0);
+        assert_eq!(error("div >").position, 5);
+        assert_eq!(error("p::before").message, "pseudo-elements are not supported");
+        assert_eq!(error("p:hover").message, "unsupported pseudo-class `:hover`");
+        assert_eq!(error("[href").position, 5);
+        assert_eq!(error("li:nth-child(x)").message, "invalid `an+b` expression");
+        assert_eq!(error("a,").position, 2);
+        assert!(Fragment::parse_str(LIST).select("ul >> li").is_err());
+    }
+}
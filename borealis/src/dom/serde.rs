@@ -0,0 +1,240 @@
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, EnumVisitor, VariantVisitor, Visitor};
+
+use string_cache::{Atom, Namespace, QualName};
+
+use super::{Handle, Node};
+
+// Nodes are serialized as an externally tagged enum, names as `(namespace, local)` pairs and
+// attributes as `(namespace, local, value)` triples. Parent links are rebuilt when deserializing.
+// The contents of a template follow its children as a `TemplateContents` node.
+const VARIANTS: &'static [&'static str] = &["CData",
+                                            "Comment",
+                                            "Doctype",
+                                            "Document",
+                                            "Element",
+                                            "ProcessingInstruction",
+                                            "TemplateContents",
+                                            "Text"];
+
+type Name = (String, String);
+type Attribute = (String, String, String);
+
+impl Serialize for Handle {
+    fn serialize<S: Serializer>(&self, s: &mut S) -> Result<(), S::Error> {
+        serialize_node(&self.borrow().0, self.template_contents(), s)
+    }
+}
+
+impl Serialize for Node {
+    fn serialize<S: Serializer>(&self, s: &mut S) -> Result<(), S::Error> {
+        serialize_node(self, None, s)
+    }
+}
+
+fn serialize_node<S: Serializer>(node: &Node,
+                                 template_contents: Option<Handle>,
+                                 s: &mut S)
+                                 -> Result<(), S::Error> {
+    match *node {
+        Node::CData(ref text) => s.serialize_newtype_variant("Node", 0, "CData", &text[..]),
+        Node::Comment(ref text) => s.serialize_newtype_variant("Node", 1, "Comment", &text[..]),
+        Node::Doctype(ref name, ref public_id, ref system_id) => {
+            let doctype = (&name[..], &public_id[..], &system_id[..]);
+            s.serialize_newtype_variant("Node", 2, "Doctype", doctype)
+        }
+        Node::Document(ref children) => {
+            s.serialize_newtype_variant("Node", 3, "Document", children)
+        }
+        Node::Element(ref name, ref attributes, ref children) => {
+            let attributes = attributes.iter()
+                                       .map(|a| (&*a.0.ns.0, &*a.0.local, &a.1[..]))
+                                       .collect::<Vec<_>>();
+            let mut children = children.clone();
+            children.extend(template_contents);
+
+            let element = ((&*name.ns.0, &*name.local), attributes, children);
+            s.serialize_newtype_variant("Node", 4, "Element", element)
+        }
+        Node::ProcessingInstruction(ref target, ref data) => {
+            let pi = (&target[..], &data[..]);
+            s.serialize_newtype_variant("Node", 5, "ProcessingInstruction", pi)
+        }
+        Node::TemplateContents(ref children) => {
+            s.serialize_newtype_variant("Node", 6, "TemplateContents", children)
+        }
+        Node::Text(ref text) => s.serialize_newtype_variant("Node", 7, "Text", &text[..]),
+    }
+}
+
+impl Deserialize for Handle {
+    fn deserialize<D: Deserializer>(d: &mut D) -> Result<Handle, D::Error> {
+        d.deserialize_enum("Node", VARIANTS, NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl EnumVisitor for NodeVisitor {
+    type Value = Handle;
+
+    fn visit<V: VariantVisitor>(&mut self, mut visitor: V) -> Result<Handle, V::Error> {
+        let variant: Variant = try!(visitor.visit_variant());
+
+        let (node, children) = match variant {
+            Variant::CData => {
+                let text: String = try!(visitor.visit_newtype());
+                (Node::CData(text.into()), Vec::new())
+            }
+            Variant::Comment => {
+                let text: String = try!(visitor.visit_newtype());
+                (Node::Comment(text.into()), Vec::new())
+            }
+            Variant::Doctype => {
+                let (name, public_id, system_id): (String, String, String) =
+                    try!(visitor.visit_newtype());
+                (Node::Doctype(name.into(), public_id.into(), system_id.into()), Vec::new())
+            }
+            Variant::Document => (Node::Document(Vec::new()), try!(visitor.visit_newtype())),
+            Variant::Element => {
+                let (name, attributes, children): (Name, Vec<Attribute>, Vec<Handle>) =
+                    try!(visitor.visit_newtype());
+                let attributes = attributes.into_iter()
+                                           .map(|(ns, local, value)| {
+                                               (qualname(ns, local), value.into())
+                                           })
+                                           .collect();
+
+                (Node::Element(qualname(name.0, name.1), attributes, Vec::new()), children)
+            }
+            Variant::ProcessingInstruction => {
+                let (target, data): (String, String) = try!(visitor.visit_newtype());
+                (Node::ProcessingInstruction(target.into(), data.into()), Vec::new())
+            }
+            Variant::TemplateContents => {
+                (Node::TemplateContents(Vec::new()), try!(visitor.visit_newtype()))
+            }
+            Variant::Text => {
+                let text: String = try!(visitor.visit_newtype());
+                (Node::Text(text.into()), Vec::new())
+            }
+        };
+
+        let handle: Handle = node.into();
+
+        for child in children {
+            let is_contents = match child.borrow().0 {
+                Node::TemplateContents(..) => true,
+                _ => false,
+            };
+
+            if let (true, Some(contents)) = (is_contents, handle.template_contents()) {
+                for child in child.children() {
+                    contents.append_child(child);
+                }
+
+                continue;
+            }
+
+            handle.append_child(child);
+        }
+
+        Ok(handle)
+    }
+}
+
+fn qualname(ns: String, local: String) -> QualName {
+    QualName::new(Namespace(Atom::from(&ns[..])), Atom::from(&local[..]))
+}
+
+enum Variant {
+    CData,
+    Comment,
+    Doctype,
+    Document,
+    Element,
+    ProcessingInstruction,
+    TemplateContents,
+    Text,
+}
+
+impl Deserialize for Variant {
+    fn deserialize<D: Deserializer>(d: &mut D) -> Result<Variant, D::Error> {
+        d.deserialize_struct_field(VariantNameVisitor)
+    }
+}
+
+struct VariantNameVisitor;
+
+impl Visitor for VariantNameVisitor {
+    type Value = Variant;
+
+    fn visit_usize<E: de::Error>(&mut self, index: usize) -> Result<Variant, E> {
+        match VARIANTS.get(index) {
+            Some(name) => self.visit_str(name),
+            None => Err(E::invalid_value(&format!("unknown node variant: {}", index))),
+        }
+    }
+
+    fn visit_str<E: de::Error>(&mut self, name: &str) -> Result<Variant, E> {
+        match name {
+            "CData" => Ok(Variant::CData),
+            "Comment" => Ok(Variant::Comment),
+            "Doctype" => Ok(Variant::Doctype),
+            "Document" => Ok(Variant::Document),
+            "Element" => Ok(Variant::Element),
+            "ProcessingInstruction" => Ok(Variant::ProcessingInstruction),
+            "TemplateContents" => Ok(Variant::TemplateContents),
+            "Text" => Ok(Variant::Text),
+            _ => Err(E::unknown_variant(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use dom::{Document, Fragment, Handle, Node};
+
+    #[test]
+    fn test_serialize_fragment() {
+        let fragment = Fragment::parse_str("<p class=\"a\">b<!--c--></p>");
+        let json = serde_json::to_string(&fragment).unwrap();
+
+        assert_eq!(json,
+                   "[{\"Element\":[[\"http://www.w3.org/1999/xhtml\",\"p\"],[[\"\",\"class\",\
+                    \"a\"]],[{\"Text\":\"b\"},{\"Comment\":\"c\"}]]}]");
+
+        let nodes = serde_json::from_str::<Fragment>(&json).unwrap().handles();
+        assert!(nodes[0].deep_eq(&fragment.handles()[0]));
+    }
+
+    #[test]
+    fn test_round_trip_document() {
+        let document = Document::parse_str("<!DOCTYPE html><title>a</title><p>b<template>c\
+                                            </template></p>");
+        let json = serde_json::to_string(&document).unwrap();
+        let handle = serde_json::from_str::<Document>(&json).unwrap().handle();
+
+        assert!(handle.deep_eq(&document.handle()));
+
+        let p = handle.select_first("p").unwrap().unwrap();
+        assert_eq!(p.parent().unwrap().parent().unwrap().parent(), Some(handle.clone()));
+
+        let contents = p.select_first("template").unwrap().unwrap().template_contents().unwrap();
+        assert_eq!(contents.children().count(), 1);
+        assert!(contents.children().all(|c| c.parent() == Some(contents.clone())));
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        assert!(serde_json::from_str::<Handle>("{\"Unknown\":\"a\"}").is_err());
+        assert!(serde_json::from_str::<Handle>("{\"Text\":[\"a\"]}").is_err());
+        assert!(serde_json::from_str::<Document>("{\"Text\":\"a\"}").is_err());
+
+        let handle = serde_json::from_str::<Handle>("{\"Text\":\"a\"}").unwrap();
+        assert_eq!(handle.borrow().0, Node::Text("a".into()));
+    }
+}
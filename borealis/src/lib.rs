@@ -2,7 +2,12 @@
 
 #[cfg(feature = "nightly")]
 extern crate test;
+extern crate encoding as enc;
 extern crate html5ever;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 #[macro_use(qualname, ns, atom)]
 extern crate string_cache as sc;
 
@@ -11,6 +16,12 @@ pub use dom::{Document, Fragment};
 pub mod dom;
 pub mod serializer;
 
+mod url;
+
+pub mod encoding {
+    pub use enc::*;
+}
+
 pub mod string_cache {
     pub use sc::*;
 }
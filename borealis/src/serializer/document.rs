@@ -11,11 +11,25 @@ pub struct DocumentSerializer<'a, 'b: 'a, 'w: 'b, W: 'w + Write> {
 }
 
 impl<'a, 'b, 'w, W: Write> DocumentSerializer<'a, 'b, 'w, W> {
+    pub fn comment(self, comment: &str) -> DocumentSerializer<'a, 'b, 'w, W> {
+        self.serializer.write_comment(comment);
+        self
+    }
+
     pub fn doctype(self, name: &str) -> DocumentDoctypeSerializer<'a, 'b, 'w, W> {
         self.serializer.write_doctype(name);
         DocumentDoctypeSerializer { internal: self }
     }
 
+    pub fn doctype_with_ids(self,
+                            name: &str,
+                            public_id: &str,
+                            system_id: &str)
+                            -> DocumentDoctypeSerializer<'a, 'b, 'w, W> {
+        self.serializer.write_doctype_with_ids(name, public_id, system_id);
+        DocumentDoctypeSerializer { internal: self }
+    }
+
     pub fn node(self) -> NodeSerializer<'a, 'b, 'w, W> {
         new_node_ser(self.serializer)
     }
@@ -1,49 +1,90 @@
 
-use std::cell::RefCell;
-use std::io::{Result, Write};
-use std::mem;
+use std::cell::Cell;
+use std::cmp;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::thread::LocalKey;
 
 use html5ever;
-use html5ever::serialize::{SerializeOpts, TraversalScope};
+use html5ever::serialize::SerializeOpts;
+
+use string_cache::QualName;
+
+use url;
 
 pub use self::document::{DocumentSerializer, DocumentDoctypeSerializer};
 pub use self::empty_attrs::EmptyAttrs;
 pub use self::node::NodeSerializer;
 use self::serializer::Serializer;
+use self::writer::Writer;
 
 mod document;
 mod empty_attrs;
 mod node;
 mod serializer;
+mod writer;
 
 pub trait SerializeDocument {
     fn serialize_document<W: Write>(self, DocumentSerializer<W>);
+
+    /// An estimate of the number of bytes written when serializing this document.
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    /// A thread local holding the size of the last document of this type, which
+    /// `to_vec_adaptive` preallocates from. Templates generate one for every type, including
+    /// types borrowing their values.
+    fn last_size(&self) -> Option<&'static LocalKey<Cell<usize>>> {
+        None
+    }
 }
 
 pub trait SerializeNode {
     fn serialize_node<W: Write>(self, &mut NodeSerializer<W>);
+
+    /// An estimate of the number of bytes written when serializing this node.
+    fn size_hint(&self) -> usize {
+        0
+    }
 }
 
 impl SerializeNode for String {
     fn serialize_node<W: Write>(self, s: &mut NodeSerializer<W>) {
         s.text(&self);
     }
+
+    fn size_hint(&self) -> usize {
+        self.len()
+    }
 }
 
 impl<'a> SerializeNode for &'a String {
     fn serialize_node<W: Write>(self, s: &mut NodeSerializer<W>) {
         s.text(&self);
     }
+
+    fn size_hint(&self) -> usize {
+        self.len()
+    }
 }
 
 impl<'a> SerializeNode for &'a str {
     fn serialize_node<W: Write>(self, s: &mut NodeSerializer<W>) {
         s.text(self);
     }
+
+    fn size_hint(&self) -> usize {
+        self.len()
+    }
 }
 
 pub trait SerializeNodes {
     fn serialize_node<W: Write>(self, &mut NodeSerializer<W>);
+
+    /// The nodes can't be iterated without consuming them, so nothing is known about their size.
+    fn size_hint(&self) -> usize {
+        0
+    }
 }
 
 impl<I: SerializeNode, T: IntoIterator<Item = I>> SerializeNodes for T {
@@ -54,40 +95,92 @@ impl<I: SerializeNode, T: IntoIterator<Item = I>> SerializeNodes for T {
     }
 }
 
-struct Serializable<T: SerializeDocument>(RefCell<Option<T>>);
-
-impl<T: SerializeDocument> html5ever::serialize::Serializable for Serializable<T> {
-    fn serialize<'w, W: Write>(&self,
-                               serializer: &mut html5ever::serialize::Serializer<'w, W>,
-                               _: TraversalScope)
-                               -> Result<()> {
-        let mut serializer = Serializer::new(serializer);
-
-        {
-            let mut doc = self.0.borrow_mut();
-            let doc = if doc.is_some() {
-                let mut x = None;
-                mem::swap(&mut (*doc), &mut x);
-                x.unwrap()
-            } else {
-                panic!("value already used");
-            };
-
-            let doc_ser = document::new_doc_ser(&mut serializer);
-            doc.serialize_document(doc_ser);
-        }
+/// Options applied to the whole document while it is serialized, including nodes serialized by
+/// templates.
+#[derive(Default)]
+pub struct RenderOpts<'a> {
+    /// Rewrites the URLs in attributes, like `href`, `src`, `srcset` and the `url()` functions in
+    /// `style`.
+    pub rewrite_url: Option<&'a Fn(&str) -> String>,
+    /// Added as the `nonce` attribute of every `<script>` and `<style>`, for a
+    /// Content-Security-Policy that allows them with `'nonce-..'`.
+    pub nonce: Option<&'a str>,
+}
 
-        serializer.error()
-    }
+/// Returns true if the render options can change the start tag of an element, by adding a
+/// nonce to a `<script>` or `<style>` or by rewriting the URLs in its attributes. Templates
+/// render everything else ahead of time.
+pub fn transforms_start_tag<'i, T>(name: &QualName, mut attrs: T) -> bool
+    where T: Iterator<Item = &'i QualName>
+{
+    takes_nonce(name) || attrs.any(url::holds_urls)
 }
 
-pub fn serialize<'w, W, T>(writer: &mut W, document: T) -> Result<()>
-    where W: 'w + Write,
+fn takes_nonce(name: &QualName) -> bool {
+    name.ns == ns!(html) && (name.local == atom!("script") || name.local == atom!("style"))
+}
+
+pub fn serialize<W, T>(writer: &mut W, document: T) -> Result<()>
+    where W: Write,
+          T: SerializeDocument
+{
+    serialize_with_opts(writer, document, &RenderOpts::default())
+}
+
+pub fn serialize_with_opts<W, T>(writer: &mut W, document: T, opts: &RenderOpts) -> Result<()>
+    where W: Write,
           T: SerializeDocument
 {
-    html5ever::serialize::serialize(writer,
-                                    &Serializable(RefCell::new(Some(document))),
-                                    SerializeOpts::default())
+    let mut writer = Writer::new(writer);
+    let raw_writer = writer.clone();
+
+    let mut inner = html5ever::serialize::Serializer::new(&mut writer, SerializeOpts::default());
+    let mut serializer = Serializer::new(&mut inner, raw_writer, opts);
+
+    {
+        let doc_ser = document::new_doc_ser(&mut serializer);
+        document.serialize_document(doc_ser);
+    }
+
+    serializer.error()
+}
+
+pub fn to_vec<T: SerializeDocument>(document: T) -> Result<Vec<u8>> {
+    let mut writer = Vec::with_capacity(document.size_hint());
+    try!(serialize(&mut writer, document));
+    Ok(writer)
+}
+
+pub fn to_string<T: SerializeDocument>(document: T) -> Result<String> {
+    let writer = try!(to_vec(document));
+    String::from_utf8(writer).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+pub fn to_string_with_opts<T: SerializeDocument>(document: T, opts: &RenderOpts) -> Result<String> {
+    let mut writer = Vec::with_capacity(document.size_hint());
+    try!(serialize_with_opts(&mut writer, document, opts));
+    String::from_utf8(writer).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Like `to_vec`, but preallocates using the size of the last document of the same type
+/// rendered on this thread if it was larger than the size hint.
+pub fn to_vec_adaptive<T: SerializeDocument>(document: T) -> Result<Vec<u8>> {
+    let last_size = document.last_size();
+    let size = last_size.map_or(0, |last_size| last_size.with(|size| size.get()));
+
+    let mut writer = Vec::with_capacity(cmp::max(document.size_hint(), size));
+    try!(serialize(&mut writer, document));
+
+    if let Some(last_size) = last_size {
+        last_size.with(|size| size.set(writer.len()));
+    }
+
+    Ok(writer)
+}
+
+pub fn to_string_adaptive<T: SerializeDocument>(document: T) -> Result<String> {
+    let writer = try!(to_vec_adaptive(document));
+    String::from_utf8(writer).map_err(|err| Error::new(ErrorKind::InvalidData, err))
 }
 
 #[cfg(test)]
@@ -122,6 +215,46 @@ mod tests {
         assert_eq!(ser(Doc), "<!DOCTYPE html><html></html>");
     }
 
+    #[test]
+    fn test_document_comments() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.comment("a").comment("b").doctype("html").node();
+                s.comment("c");
+                s.element(qualname!(html, "html"), EmptyAttrs::new());
+                s.comment("d");
+            }
+        }
+
+        assert_eq!(ser(Doc),
+                   "<!--a--><!--b--><!DOCTYPE html><!--c--><html></html><!--d-->");
+    }
+
+    #[test]
+    fn test_doctype_with_ids() {
+        struct Doc(&'static str, &'static str, &'static str);
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                s.doctype_with_ids(self.0, self.1, self.2).node();
+            }
+        }
+
+        assert_eq!(ser(Doc("html", "", "")), "<!DOCTYPE html>");
+        assert_eq!(ser(Doc("html", "", "about:legacy-compat")),
+                   "<!DOCTYPE html SYSTEM \"about:legacy-compat\">");
+        assert_eq!(ser(Doc("html",
+                           "-//W3C//DTD XHTML 1.0 Strict//EN",
+                           "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd")),
+                   "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \
+                    \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">");
+        assert_eq!(ser(Doc("html", "-//W3C//DTD HTML 4.0//EN", "")),
+                   "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.0//EN\">");
+        assert_eq!(ser(Doc("html", "", "a\"b")), "<!DOCTYPE html SYSTEM 'a\"b'>");
+    }
+
     #[test]
     fn test_nodes() {
         struct Doc;
@@ -185,6 +318,178 @@ mod tests {
                     html><html><body><p>0</p><p>1</p><p>2</p><p>3</p><p>4</p></body></html>");
     }
 
+    #[test]
+    fn test_raw() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.doctype("html").node();
+                let mut html = s.element(qualname!(html, "html"), EmptyAttrs::new());
+                html.raw("<body><p>a &amp; b</p>");
+                html.text("<c>");
+                html.raw("</body>");
+            }
+        }
+
+        assert_eq!(ser(Doc),
+                   "<!DOCTYPE html><html><body><p>a &amp; b</p>&lt;c&gt;</body></html>");
+    }
+
+    #[test]
+    fn test_cdata_and_processing_instructions() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.node();
+                let mut svg = s.element(qualname!(svg, "svg"), EmptyAttrs::new());
+                svg.processing_instruction("xml-stylesheet", "href=\"a.css\"");
+                svg.cdata("a < b");
+            }
+        }
+
+        assert_eq!(ser(Doc),
+                   "<svg><?xml-stylesheet href=\"a.css\"><![CDATA[a < b]]></svg>");
+    }
+
+    #[test]
+    fn test_cdata_end_in_text() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.node();
+                let mut svg = s.element(qualname!(svg, "svg"), EmptyAttrs::new());
+                svg.cdata("a]]>b");
+            }
+        }
+
+        assert_eq!(ser(Doc), "<svg><![CDATA[a]]]]><![CDATA[>b]]></svg>");
+    }
+
+    #[test]
+    fn test_invalid_processing_instructions() {
+        struct Doc(&'static str, &'static str);
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.node();
+                s.processing_instruction(self.0, self.1);
+            }
+        }
+
+        assert!(to_string(Doc("a", "b")).is_ok());
+        assert!(to_string(Doc("a", "b>c")).is_err());
+        assert!(to_string(Doc("a>", "b")).is_err());
+        assert!(to_string(Doc("a b", "c")).is_err());
+        assert!(to_string(Doc("", "b")).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_urls() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.node();
+                let href = qualname!("", "href");
+                let srcset = qualname!("", "srcset");
+                s.element(qualname!(html, "a"), [(&href, "/a")].iter());
+                s.element(qualname!(html, "img"), [(&srcset, "/b.png 2x")].iter());
+            }
+        }
+
+        let rewrite = |url: &str| format!("/static{}", url);
+        let opts = RenderOpts { rewrite_url: Some(&rewrite), ..RenderOpts::default() };
+
+        assert_eq!(ser(Doc), "<a href=\"/a\"></a><img srcset=\"/b.png 2x\">");
+        assert_eq!(to_string_with_opts(Doc, &opts).unwrap(),
+                   "<a href=\"/static/a\"></a><img srcset=\"/static/b.png 2x\">");
+    }
+
+    #[test]
+    fn test_transforms_start_tag() {
+        let (href, class) = (qualname!("", "href"), qualname!("", "class"));
+
+        assert!(transforms_start_tag(&qualname!(html, "a"), [&class, &href].iter().cloned()));
+        assert!(transforms_start_tag(&qualname!(html, "style"), None.into_iter()));
+        assert!(!transforms_start_tag(&qualname!(html, "p"), Some(&class).into_iter()));
+        assert!(!transforms_start_tag(&qualname!(svg, "script"), None.into_iter()));
+    }
+
+    #[test]
+    fn test_nonce() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.node();
+                let nonce = qualname!("", "nonce");
+                let src = qualname!("", "src");
+                s.element(qualname!(html, "script"), [(&src, "a.js"), (&nonce, "b")].iter());
+                s.element(qualname!(html, "style"), EmptyAttrs::new()).text("p {}");
+                s.raw("<p></p>");
+            }
+        }
+
+        let opts = RenderOpts { nonce: Some("c"), ..RenderOpts::default() };
+
+        assert_eq!(ser(Doc),
+                   "<script src=\"a.js\" nonce=\"b\"></script><style>p {}</style><p></p>");
+        assert_eq!(to_string_with_opts(Doc, &opts).unwrap(),
+                   "<script src=\"a.js\" nonce=\"c\"></script><style nonce=\"c\">p \
+                    {}</style><p></p>");
+    }
+
+    #[test]
+    fn test_to_string() {
+        struct Doc;
+
+        impl SerializeDocument for Doc {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.doctype("html").node();
+                let mut html = s.element(qualname!(html, "html"), EmptyAttrs::new());
+                html.text("hello");
+            }
+
+            fn size_hint(&self) -> usize {
+                33
+            }
+        }
+
+        assert_eq!(to_string(Doc).unwrap(), "<!DOCTYPE html><html>hello</html>");
+        assert!(to_vec(Doc).unwrap().capacity() >= 33);
+    }
+
+    #[test]
+    fn test_to_vec_adaptive() {
+        use std::cell::Cell;
+        use std::thread::LocalKey;
+
+        struct Doc<'a>(&'a str, usize);
+
+        impl<'a> SerializeDocument for Doc<'a> {
+            fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+                let mut s = s.node();
+                for _ in 0..self.1 {
+                    s.text(self.0);
+                }
+            }
+
+            fn last_size(&self) -> Option<&'static LocalKey<Cell<usize>>> {
+                thread_local!(static LAST_SIZE: Cell<usize> = Cell::new(0));
+                Some(&LAST_SIZE)
+            }
+        }
+
+        let a = "a".to_owned();
+
+        assert_eq!(to_vec_adaptive(Doc(&a, 100)).unwrap().len(), 100);
+        assert!(to_vec_adaptive(Doc(&a, 10)).unwrap().capacity() >= 100);
+        assert_eq!(to_string_adaptive(Doc(&a, 10)).unwrap(), "aaaaaaaaaa");
+    }
+
     #[cfg(feature = "nightly")]
     #[bench]
     fn bench_serialize_document(b: &mut Bencher) {
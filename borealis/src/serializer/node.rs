@@ -34,6 +34,21 @@ impl<'a, 'b: 'a, 'c: 'b, 'd: 'c, 'w: 'd, W: Write> NodeSerializer<'c, 'd, 'w, W>
         self.serializer.write_comment(comment);
     }
 
+    pub fn cdata(&mut self, text: &str) {
+        self.serializer.write_cdata(text);
+    }
+
+    pub fn processing_instruction(&mut self, target: &str, data: &str) {
+        self.serializer.write_processing_instruction(target, data);
+    }
+
+    /// Writes already serialized markup as is, without any escaping.
+    ///
+    /// The markup has to be balanced, it is not tracked by the serializer.
+    pub fn raw(&mut self, html: &str) {
+        self.serializer.write_raw(html);
+    }
+
     pub fn element<'i, I, II>(&'a mut self,
                               name: QualName,
                               attrs: I)
@@ -41,7 +56,7 @@ impl<'a, 'b: 'a, 'c: 'b, 'd: 'c, 'w: 'd, W: Write> NodeSerializer<'c, 'd, 'w, W>
         where I: Iterator<Item = II>,
               II: Into<Attr<'i>>
     {
-        self.serializer.start_elem(name.clone(),
+        self.serializer.start_elem(&name,
                                    attrs.into_iter().map(|a| {
                                        let a = a.into();
                                        (a.0, a.1)
@@ -55,8 +70,8 @@ impl<'a, 'b: 'a, 'c: 'b, 'd: 'c, 'w: 'd, W: Write> NodeSerializer<'c, 'd, 'w, W>
 
 impl<'a, 'b, 'w, W: Write> Drop for NodeSerializer<'a, 'b, 'w, W> {
     fn drop(&mut self) {
-        match self.name {
-            Some(ref name) => self.serializer.end_elem(name.clone()),
+        match self.name.take() {
+            Some(name) => self.serializer.end_elem(name),
             None => (),
         }
     }
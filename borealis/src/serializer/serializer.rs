@@ -1,23 +1,39 @@
 
-use std::io::{Error, Result, Write};
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Result, Write};
 
 use html5ever;
 
 use string_cache::QualName;
 
+use url;
+use super::RenderOpts;
+use super::writer::Writer;
+
 pub struct Serializer<'a, 'w: 'a, W: 'w + Write> {
-    inner: &'a mut html5ever::serialize::Serializer<'w, W>,
+    inner: &'a mut html5ever::serialize::Serializer<'w, Writer<'w, W>>,
+    writer: Writer<'w, W>,
+    opts: &'w RenderOpts<'w>,
     error: Option<Error>,
 }
 
 impl<'a, 'w, W: Write> Serializer<'a, 'w, W> {
-    pub fn new(ser: &'a mut html5ever::serialize::Serializer<'w, W>) -> Serializer<'a, 'w, W> {
+    pub fn new(ser: &'a mut html5ever::serialize::Serializer<'w, Writer<'w, W>>,
+               writer: Writer<'w, W>,
+               opts: &'w RenderOpts<'w>)
+               -> Serializer<'a, 'w, W> {
         Serializer {
             inner: ser,
+            writer: writer,
+            opts: opts,
             error: None,
         }
     }
 
+    fn transforms_nodes(&self) -> bool {
+        self.opts.rewrite_url.is_some() || self.opts.nonce.is_some()
+    }
+
     fn do_cond<F>(&mut self, f: F)
         where F: FnOnce(&mut Serializer<W>) -> Result<()>
     {
@@ -31,14 +47,44 @@ impl<'a, 'w, W: Write> Serializer<'a, 'w, W> {
         }
     }
 
-    pub fn start_elem<'i, T>(&mut self, name: QualName, attrs: T)
+    // html5ever takes the name by value for both the start and the end tag, so the name kept
+    // for the end tag is copied here. That is the only copy made per element, and for the
+    // static and inline atoms used by generated code it doesn't touch the atom table.
+    pub fn start_elem<'i, T>(&mut self, name: &QualName, attrs: T)
         where T: Iterator<Item = (&'i QualName, &'i str)>
     {
-        self.do_cond(|s| s.inner.start_elem(name.into(), attrs))
+        if !self.transforms_nodes() {
+            return self.do_cond(|s| s.inner.start_elem(name.clone(), attrs));
+        }
+
+        let opts = self.opts;
+        let nonce_name = qualname!("", "nonce");
+        let nonce = match opts.nonce {
+            Some(nonce) if super::takes_nonce(name) => Some(nonce),
+            _ => None,
+        };
+
+        // A nonce replaces any nonce already on the element.
+        let mut transformed = attrs.filter(|a| nonce.is_none() || *a.0 != nonce_name)
+                                   .map(|(name, value)| {
+                                       let rewritten = opts.rewrite_url.and_then(|rewrite| {
+                                           url::rewrite_attribute(name, value, rewrite)
+                                       });
+
+                                       (name, rewritten.map_or(Cow::Borrowed(value), Cow::Owned))
+                                   })
+                                   .collect::<Vec<_>>();
+
+        if let Some(nonce) = nonce {
+            transformed.push((&nonce_name, Cow::Borrowed(nonce)));
+        }
+
+        let attrs = transformed.iter().map(|a| (a.0, &a.1[..]));
+        self.do_cond(|s| s.inner.start_elem(name.clone(), attrs));
     }
 
     pub fn end_elem(&mut self, name: QualName) {
-        self.do_cond(|s| s.inner.end_elem(name.into()));
+        self.do_cond(|s| s.inner.end_elem(name));
     }
 
     pub fn write_text(&mut self, text: &str) {
@@ -49,10 +95,64 @@ impl<'a, 'w, W: Write> Serializer<'a, 'w, W> {
         self.do_cond(|s| s.inner.write_comment(comment));
     }
 
+    // A `]]>` in the text would end the section early, so the section is split around it.
+    pub fn write_cdata(&mut self, text: &str) {
+        self.do_cond(|s| {
+            write!(s.writer, "<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+        });
+    }
+
+    // There's no way to escape a `>` in a processing instruction, so it is an error.
+    pub fn write_processing_instruction(&mut self, target: &str, data: &str) {
+        self.do_cond(|s| {
+            if target.is_empty() || target.contains(|c: char| c == '>' || c.is_whitespace()) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid processing instruction target: {:?}",
+                                              target)));
+            }
+
+            if data.contains('>') {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("`>` in processing instruction data: {:?}", data)));
+            }
+
+            write!(s.writer, "<?{} {}>", target, data)
+        });
+    }
+
     pub fn write_doctype(&mut self, name: &str) {
         self.do_cond(|s| s.inner.write_doctype(name));
     }
 
+    pub fn write_doctype_with_ids(&mut self, name: &str, public_id: &str, system_id: &str) {
+        fn quote(id: &str) -> char {
+            if id.contains('"') { '\'' } else { '"' }
+        }
+
+        self.do_cond(|s| {
+            try!(write!(s.writer, "<!DOCTYPE {}", name));
+
+            if !public_id.is_empty() {
+                let q = quote(public_id);
+                try!(write!(s.writer, " PUBLIC {}{}{}", q, public_id, q));
+
+                if !system_id.is_empty() {
+                    let q = quote(system_id);
+                    try!(write!(s.writer, " {}{}{}", q, system_id, q));
+                }
+            } else if !system_id.is_empty() {
+                let q = quote(system_id);
+                try!(write!(s.writer, " SYSTEM {}{}{}", q, system_id, q));
+            }
+
+            s.writer.write_all(b">")
+        });
+    }
+
+    pub fn write_raw(&mut self, raw: &str) {
+        self.do_cond(|s| s.writer.write_all(raw.as_bytes()));
+    }
+
     pub fn error(self) -> Result<()> {
         match self.error {
             Some(err) => Err(err),
@@ -0,0 +1,136 @@
+
+use std::ascii::AsciiExt;
+
+use string_cache::QualName;
+
+// Attributes holding a single URL.
+pub const URL_ATTRIBUTES: &'static [&'static str] = &["action", "background", "cite",
+                                                      "formaction", "href", "longdesc", "poster",
+                                                      "src"];
+
+// Whether `rewrite_attribute` rewrites the URLs in the attribute.
+pub fn holds_urls(name: &QualName) -> bool {
+    name.ns == ns!("") &&
+    match &*name.local {
+        "srcset" | "style" => true,
+        local => URL_ATTRIBUTES.contains(&local),
+    }
+}
+
+// Rewrites the URLs in an attribute value, returns `None` if the attribute doesn't hold any.
+// Besides the attributes holding a single URL, this handles the candidates in `srcset` and the
+// `url()` functions in `style`.
+pub fn rewrite_attribute<F>(name: &QualName, value: &str, rewrite: &F) -> Option<String>
+    where F: ?Sized + Fn(&str) -> String
+{
+    if name.ns != ns!("") {
+        return None;
+    }
+
+    match &*name.local {
+        "srcset" => Some(rewrite_srcset(value, rewrite)),
+        "style" => Some(rewrite_css(value, rewrite)),
+        local if URL_ATTRIBUTES.contains(&local) => Some(rewrite(value.trim())),
+        _ => None,
+    }
+}
+
+// Candidates are a URL followed by an optional descriptor, like `image.png 2x`.
+fn rewrite_srcset<F>(srcset: &str, rewrite: &F) -> String
+    where F: ?Sized + Fn(&str) -> String
+{
+    let candidates = srcset.split(',')
+                           .map(|c| c.trim())
+                           .filter(|c| !c.is_empty())
+                           .map(|c| {
+                               match c.find(char::is_whitespace) {
+                                   Some(i) => format!("{} {}", rewrite(&c[..i]), c[i..].trim()),
+                                   None => rewrite(c),
+                               }
+                           })
+                           .collect::<Vec<_>>();
+
+    candidates.join(", ")
+}
+
+fn rewrite_css<F>(css: &str, rewrite: &F) -> String
+    where F: ?Sized + Fn(&str) -> String
+{
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+
+    // Lowercasing ASCII keeps the byte offsets the same.
+    while let Some(i) = rest.to_ascii_lowercase().find("url(") {
+        let start = i + 4;
+        let start = start + rest[start..].len() - rest[start..].trim_left().len();
+
+        let end = match rest[start..].chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                rest[start + 1..].find(quote).map(|j| (start + 1, start + 1 + j))
+            }
+            _ => {
+                rest[start..]
+                    .find(')')
+                    .map(|j| (start, start + rest[start..start + j].trim_right().len()))
+            }
+        };
+
+        let (start, end) = match end {
+            Some(end) => end,
+            None => break,
+        };
+
+        output.push_str(&rest[..start]);
+
+        if start < end {
+            output.push_str(&rewrite(&rest[start..end]));
+        }
+
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use string_cache::{Atom, QualName};
+
+    fn rewrite(name: &str, value: &str) -> Option<String> {
+        let name = QualName::new(ns!(""), Atom::from(name));
+        rewrite_attribute(&name, value, &|url: &str| format!("/cdn{}", url))
+    }
+
+    #[test]
+    fn test_rewrite_attribute() {
+        assert_eq!(rewrite("href", " /a.html "), Some("/cdn/a.html".to_owned()));
+        assert_eq!(rewrite("src", "/a.png"), Some("/cdn/a.png".to_owned()));
+        assert_eq!(rewrite("longdesc", "/a.html"), Some("/cdn/a.html".to_owned()));
+        assert_eq!(rewrite("title", "/a"), None);
+        assert_eq!(rewrite_attribute(&qualname!(html, "href"), "/a", &|url: &str| url.to_owned()),
+                   None);
+    }
+
+    #[test]
+    fn test_rewrite_srcset() {
+        assert_eq!(rewrite("srcset", "/a.png, /b.png 2x,/c.png  100w"),
+                   Some("/cdn/a.png, /cdn/b.png 2x, /cdn/c.png 100w".to_owned()));
+    }
+
+    #[test]
+    fn test_rewrite_css() {
+        assert_eq!(rewrite("style",
+                           "background: URL( /a.png ) no-repeat; cursor: url(\"/b.cur\"), \
+                            url('/c(1).cur'), auto"),
+                   Some("background: URL( /cdn/a.png ) no-repeat; cursor: url(\"/cdn/b.cur\"), \
+                         url('/cdn/c(1).cur'), auto"
+                            .to_owned()));
+        assert_eq!(rewrite("style", "color: red; background: url()"),
+                   Some("color: red; background: url()".to_owned()));
+        assert_eq!(rewrite("style", "background: url(/a.png"),
+                   Some("background: url(/a.png".to_owned()));
+    }
+}
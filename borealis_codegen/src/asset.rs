@@ -0,0 +1,218 @@
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use borealis::dom::{Handle, Node};
+
+use syntax::codemap::Span;
+use syntax::ext::base::ExtCtxt;
+
+use hash::{fingerprint, sha256};
+use template::Template;
+
+// Resolves the local files referenced by the template at compile time. `{{ asset!("app.css") }}`
+// becomes the path with a hash of the file in the query, so that the URL changes along with the
+// file, and `integrity="auto"` becomes the subresource integrity of the file in `src` or `href`.
+//
+// Paths are relative to the template, and an absolute path or a missing file is an error. A query
+// or fragment in the path is kept, the hash is added to the query.
+pub fn resolve_assets(cx: &ExtCtxt, template: &Template, nodes: &[Handle]) {
+    for node in nodes.iter().flat_map(|n| Some(n.clone()).into_iter().chain(n.descendants())) {
+        let is_element = match node.borrow().0 {
+            Node::Element(..) => true,
+            _ => false,
+        };
+
+        if is_element {
+            resolve_attributes(cx, template, &node);
+        } else {
+            resolve_text(cx, template, &node);
+        }
+
+        if let Some(contents) = node.template_contents() {
+            resolve_assets(cx, template, &contents.children().collect::<Vec<_>>());
+        }
+    }
+}
+
+fn resolve_text(cx: &ExtCtxt, template: &Template, node: &Handle) {
+    let text = match node.borrow().0 {
+        Node::Text(ref text) => text.to_string(),
+        _ => return,
+    };
+
+    if let Some(text) = replace_assets(cx, template, node, None, &text) {
+        node.set_text(text.into());
+    }
+}
+
+fn resolve_attributes(cx: &ExtCtxt, template: &Template, node: &Handle) {
+    let attributes = match node.borrow().0 {
+        Node::Element(_, ref attributes, _) => attributes.clone(),
+        _ => return,
+    };
+
+    let mut url = None;
+    let mut integrity = None;
+
+    for (name, value) in attributes {
+        let value = match replace_assets(cx, template, node, Some(&*name.local), &value) {
+            Some(value) => {
+                node.set_attribute(name.clone(), value.clone().into());
+                value
+            }
+            None => value.to_string(),
+        };
+
+        let is_url = &*name.local == "href" || &*name.local == "src";
+
+        if is_url && url.is_none() {
+            url = Some(value);
+        } else if &*name.local == "integrity" && value == "auto" {
+            integrity = Some(name);
+        }
+    }
+
+    let integrity = match integrity {
+        Some(integrity) => integrity,
+        None => return,
+    };
+
+    let span = template.attribute_span(node, "integrity", "integrity");
+
+    let url = match url {
+        Some(ref url) if !url.starts_with("{{") && !url.contains("//") => url,
+        _ => {
+            template.span_err(cx,
+                              span,
+                              "`integrity=\"auto\"` requires a `src` or `href` with the path of \
+                               a local file");
+            return;
+        }
+    };
+
+    if let Some(contents) = read_asset(cx, template, span, split_path(url).0) {
+        node.set_attribute(integrity, sha256(&contents).into());
+    }
+}
+
+// Replaces every `{{ asset!("..") }}` in `s`, the text of `node` or the value of its
+// `attribute`, returns `None` if there aren't any.
+fn replace_assets(cx: &ExtCtxt,
+                  template: &Template,
+                  node: &Handle,
+                  attribute: Option<&str>,
+                  s: &str)
+                  -> Option<String> {
+    let regex = regex!(r#"\{\{\s*asset!\(\s*"([^"]*)"\s*\)\s*\}\}"#);
+
+    let mut replaced = String::with_capacity(s.len());
+    let mut last_end = 0;
+
+    for captures in regex.captures_iter(s) {
+        let (start, end) = captures.pos(0).unwrap();
+        let path = captures.at(1).unwrap();
+        let span = match attribute {
+            Some(name) => template.attribute_span(node, name, &s[start..end]),
+            None => template.snippet_span(node, &s[start..end]),
+        };
+
+        replaced.push_str(&s[last_end..start]);
+
+        match read_asset(cx, template, span, split_path(path).0) {
+            Some(contents) => replaced.push_str(&versioned_path(path, &fingerprint(&contents))),
+            None => replaced.push_str(path),
+        }
+
+        last_end = end;
+    }
+
+    if last_end == 0 {
+        return None;
+    }
+
+    replaced.push_str(&s[last_end..]);
+    Some(replaced)
+}
+
+// Splits a path into the file and the query and fragment following it.
+fn split_path(path: &str) -> (&str, &str) {
+    let end = path.find(|c: char| c == '?' || c == '#').unwrap_or(path.len());
+    (&path[..end], &path[end..])
+}
+
+// Adds the fingerprint to the query of the path, in front of the fragment.
+fn versioned_path(path: &str, fingerprint: &str) -> String {
+    let (file, rest) = split_path(path);
+    let (query, fragment) = rest.split_at(rest.find('#').unwrap_or(rest.len()));
+    let separator = if query.is_empty() { "?" } else { "&" };
+
+    format!("{}{}{}v={}{}", file, query, separator, fingerprint, fragment)
+}
+
+fn asset_filename(template: &Template, path: &str) -> Result<PathBuf, String> {
+    if Path::new(path).has_root() {
+        return Err(format!("asset paths are relative to the template, got {:?}", path));
+    }
+
+    Ok(template.dir().join(path))
+}
+
+fn read_asset(cx: &ExtCtxt, template: &Template, span: Span, path: &str) -> Option<Vec<u8>> {
+    let filename = match asset_filename(template, path) {
+        Ok(filename) => filename,
+        Err(err) => {
+            template.span_err(cx, span, &err);
+            return None;
+        }
+    };
+    let mut contents = Vec::new();
+
+    match File::open(&filename).and_then(|mut file| file.read_to_end(&mut contents)) {
+        Ok(_) => {
+            // Added to the codemap so that the crate is rebuilt when the file changes.
+            cx.codemap().new_filemap(filename.to_string_lossy().into_owned(),
+                                     String::from_utf8_lossy(&contents).into_owned());
+            Some(contents)
+        }
+        Err(err) => {
+            template.span_err(cx,
+                              span,
+                              &format!("couldn't read the asset {:?}: {}", filename, err));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{asset_filename, versioned_path};
+
+    use template::Template;
+
+    use syntax::codemap::{CodeMap, DUMMY_SP};
+
+    #[test]
+    fn test_asset_filename() {
+        let codemap = CodeMap::new();
+        let filemap = codemap.new_filemap("templates/test.html".to_owned(), "".to_owned());
+        let template = Template::new(filemap, false, DUMMY_SP);
+
+        assert_eq!(asset_filename(&template, "app.css").unwrap(),
+                   Path::new("templates/app.css"));
+        assert_eq!(asset_filename(&template, "../app.css").unwrap(),
+                   Path::new("templates/../app.css"));
+        assert!(asset_filename(&template, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_versioned_path() {
+        assert_eq!(versioned_path("app.css", "abc"), "app.css?v=abc");
+        assert_eq!(versioned_path("app.css?media=print", "abc"), "app.css?media=print&v=abc");
+        assert_eq!(versioned_path("app.svg#icon", "abc"), "app.svg?v=abc#icon");
+        assert_eq!(versioned_path("app.svg?a#icon", "abc"), "app.svg?a&v=abc#icon");
+    }
+}
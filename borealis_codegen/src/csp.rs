@@ -0,0 +1,105 @@
+
+use aster::AstBuilder;
+
+use borealis::dom::{Handle, Node};
+
+use syntax::ast::{Generics, Item};
+use syntax::ext::base::ExtCtxt;
+use syntax::ptr::P;
+
+use hash::sha256;
+use template::Template;
+
+// Hashes the contents of every inline `<script>` and `<style>`, for a Content-Security-Policy
+// that allows them with hash sources instead of nonces.
+pub fn inline_hashes(cx: &ExtCtxt, template: &Template, nodes: &[Handle]) -> Vec<String> {
+    let mut hashes = Vec::new();
+
+    for node in nodes.iter().flat_map(|n| Some(n.clone()).into_iter().chain(n.descendants())) {
+        if let Some(content) = inline_content(&node) {
+            if content.contains("{{") {
+                // The span of the text with the interpolation, not of anything in the start tag.
+                let text = node.children()
+                               .find(|c| {
+                                   match c.borrow().0 {
+                                       Node::Text(ref text) => text.contains("{{"),
+                                       _ => false,
+                                   }
+                               })
+                               .unwrap_or_else(|| node.clone());
+
+                cx.span_warn(template.snippet_span(&text, "{{"),
+                             "the contents of this element are only known when rendering, so \
+                              they are left out of `csp_hashes()`");
+            } else {
+                hashes.push(sha256(content.as_bytes()));
+            }
+        }
+
+        // Scripts and styles in templates run once the contents are inserted into the page.
+        if let Some(contents) = node.template_contents() {
+            hashes.extend(inline_hashes(cx, template, &contents.children().collect::<Vec<_>>()));
+        }
+    }
+
+    hashes
+}
+
+fn inline_content(node: &Handle) -> Option<String> {
+    match node.borrow().0 {
+        Node::Element(ref name, ref attributes, ref children) => {
+            let is_inline = &*name.ns.0 == "http://www.w3.org/1999/xhtml" &&
+                            (&*name.local == "script" || &*name.local == "style") &&
+                            !attributes.iter().any(|a| &*a.0.local == "src");
+
+            if !is_inline {
+                return None;
+            }
+
+            let mut content = String::new();
+
+            for child in children.iter() {
+                if let Node::Text(ref text) = child.borrow().0 {
+                    content.push_str(text);
+                }
+            }
+
+            Some(content)
+        }
+        _ => None,
+    }
+}
+
+// Builds `csp_hashes()` on the template, returning the hashes in the order the elements appear.
+pub fn csp_hashes_item(cx: &ExtCtxt,
+                       builder: &AstBuilder,
+                       item: &Item,
+                       generics: &Generics,
+                       hashes: &[String])
+                       -> P<Item> {
+    let generics = builder.from_generics(generics.clone()).build();
+    let ty = builder.ty()
+                    .path()
+                    .segment(item.ident)
+                    .with_generics(generics.clone())
+                    .build()
+                    .build();
+
+    let where_clause = &generics.where_clause;
+    let hashes = builder.expr()
+                        .slice()
+                        .with_exprs(hashes.iter().map(|h| builder.expr().str(&h[..])))
+                        .build();
+
+    quote_item!(cx,
+        impl $generics $ty
+            $where_clause
+        {
+            pub fn csp_hashes() -> &'static [&'static str] {
+                const HASHES: &'static [&'static str] = &$hashes;
+                HASHES
+            }
+        }
+    )
+        .unwrap()
+}
@@ -1,13 +1,15 @@
 
 use aster::AstBuilder;
 
-use borealis::string_cache::QualName;
+use borealis::dom::Handle;
+use borealis::string_cache::{Atom, QualName};
 
 use syntax::ast::Expr;
 use syntax::ext::base::ExtCtxt;
-use syntax::ext::quote::rt::ExtParseUtils;
 use syntax::ptr::P;
 
+use template::Template;
+
 pub fn string_expr<'a, T: Into<String> + Clone>(builder: &AstBuilder, s: &'a T) -> P<Expr> {
     let s: String = s.clone().into();
     builder.expr().str(&s[..])
@@ -15,15 +17,19 @@ pub fn string_expr<'a, T: Into<String> + Clone>(builder: &AstBuilder, s: &'a T)
 
 pub fn string_code_expr<'a, T: Into<String> + Clone>(cx: &ExtCtxt,
                                                      builder: &AstBuilder,
+                                                     template: &Template,
+                                                     node: &Handle,
+                                                     name: &str,
                                                      s: &'a T)
                                                      -> P<Expr> {
     let s: String = s.clone().into();
 
-    if s.starts_with("{{") && s.ends_with("}}") {
-        let s = s[2..s.len() - 2].to_owned();
-        cx.parse_expr(s)
+    if s.starts_with("{{") && s.ends_with("}}") && s.len() >= 4 {
+        let span = template.attribute_span(node, name, &s);
+        template.parse_expr(cx, span, &s[2..s.len() - 2])
     } else if s.starts_with("{{") {
-        panic!("unmatched {{");
+        template.span_err(cx, template.attribute_span(node, name, &s), "unmatched `{{`");
+        builder.expr().str(&s[..])
     } else {
         builder.expr().str(&s[..])
     }
@@ -33,14 +39,28 @@ pub fn str_expr(builder: &AstBuilder, s: &str) -> P<Expr> {
     builder.expr().str(s)
 }
 
+// Atoms are interned from their string once per thread. The data of an atom in the plugin can't
+// be copied into the generated code, since the crate being compiled may use another version of
+// string_cache, with other static atoms, or target another platform. Interning a static atom
+// is only a lookup, and clones of it are free.
+pub fn atom_expr(cx: &ExtCtxt, builder: &AstBuilder, atom: &Atom) -> P<Expr> {
+    let s = str_expr(builder, atom);
+
+    quote_expr!(cx, {
+        thread_local!(static ATOM: ::borealis::string_cache::Atom =
+            ::borealis::string_cache::Atom::from($s));
+        ATOM.with(|atom| atom.clone())
+    })
+}
+
 pub fn qualname_expr(cx: &ExtCtxt, builder: &AstBuilder, q: &QualName) -> P<Expr> {
-    let ns = str_expr(builder, &q.ns.0);
-    let local = str_expr(builder, &q.local);
+    let ns = atom_expr(cx, builder, &q.ns.0);
+    let local = atom_expr(cx, builder, &q.local);
 
     quote_expr!(cx, {
-        ::borealis::string_cache::QualName::new(
-            ::borealis::string_cache::Namespace($ns.into()),
-            $local.into()
-        )
+        ::borealis::string_cache::QualName {
+            ns: ::borealis::string_cache::Namespace($ns),
+            local: $local,
+        }
     })
 }
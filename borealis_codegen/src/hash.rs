@@ -0,0 +1,26 @@
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+
+use rustc_serialize::base64::{STANDARD, ToBase64};
+use rustc_serialize::hex::ToHex;
+
+fn digest(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+
+    let mut digest = [0; 32];
+    hasher.result(&mut digest);
+    digest
+}
+
+// The base64 SHA-256 digest prefixed with `sha256-`, the format used both by
+// Content-Security-Policy hash sources and by subresource integrity.
+pub fn sha256(data: &[u8]) -> String {
+    format!("sha256-{}", digest(data).to_base64(STANDARD))
+}
+
+// A short hex digest, for cache busting URLs.
+pub fn fingerprint(data: &[u8]) -> String {
+    digest(data)[..8].to_hex()
+}
@@ -1,7 +1,11 @@
 
+use std::io::Write;
+
 use aster::AstBuilder;
 
 use borealis::dom::{Handle, Node};
+use borealis::serializer::{self, SerializeDocument, SerializeNode, DocumentSerializer};
+use borealis::string_cache::QualName;
 
 use syntax::ast::Expr;
 use syntax::ext::base::ExtCtxt;
@@ -9,56 +13,120 @@ use syntax::ext::quote::rt::ExtParseUtils;
 use syntax::ptr::P;
 
 use expr::{string_expr, string_code_expr, str_expr, qualname_expr};
+use template::Template;
 
-pub fn document_expression(cx: &ExtCtxt, builder: &AstBuilder, document: &Handle) -> P<Expr> {
+pub fn document_expression(cx: &ExtCtxt,
+                           builder: &AstBuilder,
+                           template: &Template,
+                           document: &Handle)
+                           -> P<Expr> {
     match *document.borrow() {
-        (Node::Document(ref doctype, ref child), _) => {
-            let child_expr = match *child {
-                Some(ref child) => node_expression(cx, builder, child),
-                None => quote_expr!(cx, {}),
-            };
+        (Node::Document(ref children), _) => {
+            let mut s_expr = quote_expr!(cx, s);
+            let mut children = &children[..];
 
-            let doctype_expr = match *doctype {
-                Some(ref doctype) => {
-                    let doctype = string_expr(builder, doctype);
-                    quote_expr!(cx, s.doctype($doctype).node())
+            while let Some((child, rest)) = children.split_first() {
+                match *child.borrow() {
+                    (Node::Comment(ref comment), _) => {
+                        let comment = string_expr(builder, comment);
+                        s_expr = quote_expr!(cx, $s_expr.comment($comment));
+                    }
+                    _ => break,
                 }
-                None => quote_expr!(cx, s.node()),
-            };
 
-            match *child {
-                Some(_) => {
-                    quote_expr!(cx, {
-                        let mut s = $doctype_expr;
-                        $child_expr
-                    })
-                }
-                None => {
-                    quote_expr!(cx, {
-                        $doctype_expr;
-                    })
+                children = rest;
+            }
+
+            let node_expr = match children.split_first() {
+                Some((child, rest)) if is_doctype(child) => {
+                    children = rest;
+                    doctype_expression(cx, builder, s_expr, child)
                 }
+                _ => quote_expr!(cx, $s_expr.node()),
+            };
+
+            if children.is_empty() {
+                quote_expr!(cx, {
+                    $node_expr;
+                })
+            } else {
+                let child_exprs = nodes_expressions(cx, builder, template, children, false);
+                quote_expr!(cx, {
+                    let mut s = $node_expr;
+                    $child_exprs
+                })
             }
         }
         _ => panic!("expected document, got: {:?}", document),
     }
 }
 
-pub fn node_expression(cx: &ExtCtxt, builder: &AstBuilder, node: &Handle) -> P<Expr> {
+fn is_doctype(node: &Handle) -> bool {
     match *node.borrow() {
+        (Node::Doctype(..), _) => true,
+        _ => false,
+    }
+}
+
+fn doctype_expression(cx: &ExtCtxt,
+                      builder: &AstBuilder,
+                      s_expr: P<Expr>,
+                      doctype: &Handle)
+                      -> P<Expr> {
+    match *doctype.borrow() {
+        (Node::Doctype(ref name, ref public_id, ref system_id), _) => {
+            let name = string_expr(builder, name);
+
+            if public_id.is_empty() && system_id.is_empty() {
+                quote_expr!(cx, $s_expr.doctype($name).node())
+            } else {
+                let public_id = string_expr(builder, public_id);
+                let system_id = string_expr(builder, system_id);
+                quote_expr!(cx, $s_expr.doctype_with_ids($name, $public_id, $system_id).node())
+            }
+        }
+        _ => panic!("expected doctype, got: {:?}", doctype),
+    }
+}
+
+pub fn node_expression(cx: &ExtCtxt,
+                       builder: &AstBuilder,
+                       template: &Template,
+                       node: &Handle)
+                       -> P<Expr> {
+    match *node.borrow() {
+        (Node::CData(ref text), _) => {
+            let text = string_expr(builder, text);
+            quote_expr!(cx, {
+                s.cdata($text);
+            })
+        }
         (Node::Comment(ref comment), _) => {
             let comment = string_expr(builder, comment);
             quote_expr!(cx, {
                 s.comment($comment);
             })
         }
-        (Node::Text(ref text), _) => text_node_expression(cx, builder, &text[..]),
+        (Node::ProcessingInstruction(ref target, ref data), _) => {
+            let target = string_expr(builder, target);
+            let data = string_expr(builder, data);
+            quote_expr!(cx, {
+                s.processing_instruction($target, $data);
+            })
+        }
+        (Node::Text(ref text), _) => text_node_expression(cx, builder, template, node, &text[..]),
         (Node::Element(ref name, ref attrs, ref children), _) => {
+            let children = element_children(node, children);
+            let child_exprs = nodes_expressions(cx,
+                                                builder,
+                                                template,
+                                                &children,
+                                                !has_raw_text(name));
             let name = qualname_expr(cx, builder, name);
 
             let attrs_expr = attrs.iter().map(|a| {
                 let key = qualname_expr(cx, builder, &a.0);
-                let value = string_code_expr(cx, builder, &a.1);
+                let value = string_code_expr(cx, builder, template, node, &a.0.local, &a.1);
 
                 quote_expr!(cx, {
                     (&$key, $value)
@@ -66,9 +134,6 @@ pub fn node_expression(cx: &ExtCtxt, builder: &AstBuilder, node: &Handle) -> P<E
             });
             let attrs_expr = builder.expr().slice().with_exprs(attrs_expr).build();
 
-            let child_exprs: Vec<_> = children.iter()
-                                              .map(|c| node_expression(cx, builder, c))
-                                              .collect();
             let expr = quote_expr!(cx, {
                 s.element($name, $attrs_expr.iter())
             });
@@ -84,12 +149,255 @@ pub fn node_expression(cx: &ExtCtxt, builder: &AstBuilder, node: &Handle) -> P<E
                 })
             }
         }
-        _ => panic!("expected comment, text or element, got {:?}", node),
+        (Node::TemplateContents(ref children), _) => {
+            let child_exprs = nodes_expressions(cx, builder, template, children, true);
+            quote_expr!(cx, {
+                $child_exprs
+            })
+        }
+        _ => {
+            panic!("expected cdata, comment, processing instruction, text, element or template \
+                    contents, got {:?}",
+                   node)
+        }
     }
 }
 
-pub fn text_node_expression(cx: &ExtCtxt, builder: &AstBuilder, string: &str) -> P<Expr> {
+// Builds expressions for a list of sibling nodes, where every run of nodes without any
+// interpolation is rendered ahead of time and written with a single call to `s.raw`.
+// Elements whose start tag the render options can change are left out of those runs and
+// written with `s.element`, along with their ancestors, while their static descendants are
+// rendered ahead of time again.
+//
+// Text is only rendered ahead of time when its parent is known and escapes text, since
+// the escaping depends on the parent element.
+pub fn nodes_expressions(cx: &ExtCtxt,
+                         builder: &AstBuilder,
+                         template: &Template,
+                         nodes: &[Handle],
+                         escaped_text: bool)
+                         -> Vec<P<Expr>> {
+    fn flush_static(cx: &ExtCtxt,
+                    builder: &AstBuilder,
+                    exprs: &mut Vec<P<Expr>>,
+                    nodes: &mut Vec<Handle>) {
+        if nodes.is_empty() {
+            return;
+        }
+
+        let html = str_expr(builder, &render_static(nodes));
+        exprs.push(quote_expr!(cx, {
+            s.raw($html);
+        }));
+
+        nodes.clear();
+    }
+
+    let mut exprs = Vec::new();
+    let mut static_nodes = Vec::new();
+
+    for node in nodes.iter() {
+        let is_text = match *node.borrow() {
+            (Node::Text(_), _) => true,
+            _ => false,
+        };
+
+        if is_static(node) && !is_transformed(node) && (escaped_text || !is_text) {
+            static_nodes.push(node.clone());
+        } else {
+            flush_static(cx, builder, &mut exprs, &mut static_nodes);
+            exprs.push(node_expression(cx, builder, template, node));
+        }
+    }
+
+    flush_static(cx, builder, &mut exprs, &mut static_nodes);
+
+    exprs
+}
+
+fn is_static(node: &Handle) -> bool {
+    match *node.borrow() {
+        (Node::CData(_), _) |
+        (Node::Comment(_), _) |
+        (Node::ProcessingInstruction(..), _) => true,
+        (Node::Text(ref text), _) => !text.contains("{{"),
+        (Node::Element(_, ref attrs, ref children), _) => {
+            attrs.iter().all(|a| !a.1.starts_with("{{")) &&
+            element_children(node, children).iter().all(is_static)
+        }
+        (Node::TemplateContents(ref children), _) => children.iter().all(is_static),
+        _ => false,
+    }
+}
+
+// Whether the render options can change the start tag of the node or of any of its descendants.
+fn is_transformed(node: &Handle) -> bool {
+    match *node.borrow() {
+        (Node::Element(ref name, ref attrs, ref children), _) => {
+            serializer::transforms_start_tag(name, attrs.iter().map(|a| &a.0)) ||
+            element_children(node, children).iter().any(is_transformed)
+        }
+        (Node::TemplateContents(ref children), _) => children.iter().any(is_transformed),
+        _ => false,
+    }
+}
+
+// The children of an element followed by the contents of a template, which are serialized
+// inside the element too.
+fn element_children(element: &Handle, children: &[Handle]) -> Vec<Handle> {
+    let mut children = children.to_vec();
+    children.extend(element.template_contents().into_iter().flat_map(|c| c.children()));
+    children
+}
+
+fn has_raw_text(name: &QualName) -> bool {
+    if &*name.ns.0 != "http://www.w3.org/1999/xhtml" {
+        return false;
+    }
+
+    match &*name.local {
+        "style" | "script" | "xmp" | "iframe" | "noembed" | "noframes" | "plaintext" |
+        "noscript" => true,
+        _ => false,
+    }
+}
+
+fn render_static(nodes: &[Handle]) -> String {
+    struct StaticNodes<'a>(&'a [Handle]);
+
+    impl<'a> SerializeDocument for StaticNodes<'a> {
+        fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+            let mut s = s.node();
+
+            for node in self.0.iter() {
+                node.serialize_node(&mut s);
+            }
+        }
+    }
+
+    let mut w = Vec::new();
+    serializer::serialize(&mut w, StaticNodes(nodes)).unwrap();
+    String::from_utf8(w).unwrap()
+}
+
+// A rough guess of the number of bytes written by each interpolation that isn't a field.
+const INTERPOLATION_SIZE_HINT: usize = 16;
+
+// The size hints of the static parts of a template and of the fields it interpolates, which
+// are asked for their own size hint when rendering.
+pub fn size_hint_expr(cx: &ExtCtxt,
+                      builder: &AstBuilder,
+                      size: usize,
+                      fields: &[String])
+                      -> P<Expr> {
+    let size = builder.expr().usize(size);
+    let size = fields.iter().fold(size, |size, field| {
+        let field = cx.parse_expr(field.clone());
+        quote_expr!(cx, $size + $field.size_hint())
+    });
+
+    quote_expr!(cx, {
+        #[allow(unused_imports)]
+        use ::borealis::serializer::{SerializeNode, SerializeNodes};
+        $size
+    })
+}
+
+pub fn document_size_hint(document: &Handle, fields: &mut Vec<String>) -> usize {
+    match *document.borrow() {
+        (Node::Document(ref children), _) => nodes_size_hint(children, fields),
+        _ => panic!("expected document, got: {:?}", document),
+    }
+}
+
+fn doctype_size_hint(doctype: &Handle) -> usize {
+    match *doctype.borrow() {
+        (Node::Doctype(ref name, ref public_id, ref system_id), _) => {
+            // `<!DOCTYPE name>`
+            let mut size = name.len() + 11;
+
+            // ` PUBLIC "public_id" "system_id"` or ` SYSTEM "system_id"`
+            if !public_id.is_empty() {
+                size += public_id.len() + 10;
+
+                if !system_id.is_empty() {
+                    size += system_id.len() + 3;
+                }
+            } else if !system_id.is_empty() {
+                size += system_id.len() + 10;
+            }
+
+            size
+        }
+        _ => panic!("expected doctype, got: {:?}", doctype),
+    }
+}
+
+pub fn nodes_size_hint(nodes: &[Handle], fields: &mut Vec<String>) -> usize {
+    nodes.iter().fold(0, |size, node| size + node_size_hint(node, fields))
+}
+
+fn node_size_hint(node: &Handle, fields: &mut Vec<String>) -> usize {
+    if is_static(node) {
+        return render_static(&[node.clone()]).len();
+    }
+
+    match *node.borrow() {
+        (Node::Doctype(..), _) => doctype_size_hint(node),
+        (Node::Text(ref text), _) => {
+            let mut size = text.len();
+
+            for (start, end) in interpolations(text) {
+                size -= end - start;
+
+                match field(&text[start..end]) {
+                    Some(field) => fields.push(field.to_owned()),
+                    None => size += INTERPOLATION_SIZE_HINT,
+                }
+            }
+
+            size
+        }
+        (Node::Element(ref name, ref attrs, ref children), _) => {
+            let attrs = attrs.iter().fold(0, |size, a| {
+                let value = if a.1.starts_with("{{") {
+                    INTERPOLATION_SIZE_HINT
+                } else {
+                    a.1.len()
+                };
+
+                // ` name="value"`
+                size + a.0.local.len() + value + 4
+            });
+
+            // `<name>` and `</name>`
+            let children = nodes_size_hint(&element_children(node, children), fields);
+
+            2 * name.local.len() + 5 + attrs + children
+        }
+        (Node::TemplateContents(ref children), _) => nodes_size_hint(children, fields),
+        _ => 0,
+    }
+}
+
+// The field in an interpolation like `{{ self.value }}` or `{{ self.value.clone() }}`, which
+// can be asked for its size hint without moving it or running any other code.
+fn field(interpolation: &str) -> Option<&str> {
+    let regex = regex!(r"^\{\{\s*(self(\.[A-Za-z_][A-Za-z0-9_]*)+)(\.clone\(\))?\s*\}\}$");
+    regex.captures(interpolation).and_then(|c| c.at(1))
+}
+
+fn interpolations(string: &str) -> Vec<(usize, usize)> {
     let regex = regex!(r#"\{{2}([^"]|"(\\"|[^"])*")*?(\}{2}|"([^"]|\\")*$|$)"#);
+    regex.find_iter(string).collect()
+}
+
+pub fn text_node_expression(cx: &ExtCtxt,
+                            builder: &AstBuilder,
+                            template: &Template,
+                            node: &Handle,
+                            string: &str)
+                            -> P<Expr> {
     let mut last_end = 0;
     let mut exprs = Vec::new();
 
@@ -100,16 +408,17 @@ pub fn text_node_expression(cx: &ExtCtxt, builder: &AstBuilder, string: &str) ->
         }));
     };
 
-    for (start, end) in regex.find_iter(&string[..]) {
+    for (start, end) in interpolations(string) {
         if last_end != start {
             add_text_node_str(cx, builder, &mut exprs, &string[last_end..start]);
         }
 
+        let span = template.snippet_span(node, &string[start..end]);
+
         if !string[start + 2..end].ends_with("}}") {
-            cx.span_err(cx.original_span(),
-                        &format!("unmatched {} around: {}", "{{", &string[start..end]));
+            template.span_err(cx, span, "unmatched `{{`");
         } else {
-            let expr = cx.parse_expr(string[start + 2..end - 2].to_owned());
+            let expr = template.parse_expr(cx, span, &string[start + 2..end - 2]);
             exprs.push(quote_expr!(cx, {
                 #[allow(unused_imports)]
                 use ::borealis::serializer::{SerializeNode, SerializeNodes};
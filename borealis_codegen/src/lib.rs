@@ -3,17 +3,19 @@
 
 extern crate aster;
 extern crate borealis;
+extern crate crypto;
 extern crate quasi;
 extern crate regex;
 extern crate rustc;
 extern crate rustc_plugin;
+extern crate rustc_serialize;
 extern crate string_cache;
 #[macro_use]
 extern crate syntax;
 
 use borealis::{Document, Fragment};
+use borealis::string_cache::{Atom, Namespace, QualName};
 
-use std::rc::Rc;
 use std::path::Path;
 
 use syntax::ast::{Item, ItemKind, LitKind, MetaItem};
@@ -25,17 +27,25 @@ use syntax::ptr::P;
 use rustc_plugin::Registry;
 
 use annotation::Annotation;
-use html_expr::{document_expression, node_expression};
+use asset::resolve_assets;
+use csp::{csp_hashes_item, inline_hashes};
+use html_expr::{document_expression, document_size_hint, nodes_expressions, nodes_size_hint,
+                size_hint_expr};
+use template::Template;
 
 mod annotation;
+mod asset;
+mod csp;
 mod expr;
+mod hash;
 mod html_expr;
+mod template;
 
-fn get_file_argument<'a>(annotation: &'a Annotation) -> Option<&'a InternedString> {
+fn get_file_argument<'a>(annotation: &'a Annotation) -> Option<(&'a InternedString, Span)> {
     match annotation.find_value("file") {
         Some(lit) => {
             match lit.node {
-                LitKind::Str(ref s, _) => Some(s),
+                LitKind::Str(ref s, _) => Some((s, lit.span)),
                 _ => None,
             }
         }
@@ -43,11 +53,54 @@ fn get_file_argument<'a>(annotation: &'a Annotation) -> Option<&'a InternedStrin
     }
 }
 
-fn get_file(cx: &ExtCtxt, item: &Item, annotation: &Annotation) -> Result<Rc<String>, ()> {
+// Parses the `context` argument of `#[template_fragment(..)]`, which is either a local name
+// like `tbody` or a local name with a namespace prefix like `svg:g`.
+fn get_context(cx: &ExtCtxt, item: &Item, annotation: &Annotation) -> Result<QualName, ()> {
+    const HTML: &'static str = "http://www.w3.org/1999/xhtml";
+    const SVG: &'static str = "http://www.w3.org/2000/svg";
+    const MATHML: &'static str = "http://www.w3.org/1998/Math/MathML";
+
+    let context = match annotation.find_value("context") {
+        Some(lit) => {
+            match lit.node {
+                LitKind::Str(ref s, _) => s,
+                _ => {
+                    cx.span_err(item.span,
+                                "`#[template_fragment(..)]` requires context argument of the type \
+                                 string");
+                    return Err(());
+                }
+            }
+        }
+        None => return Ok(QualName::new(Namespace(Atom::from(HTML)), Atom::from("body"))),
+    };
+
+    let (prefix, local) = match context.find(':') {
+        Some(index) => (&context[..index], &context[index + 1..]),
+        None => ("", &context[..]),
+    };
+
+    let ns = match (prefix, local) {
+        ("", "svg") | ("svg", _) => SVG,
+        ("", "math") | ("math", _) => MATHML,
+        ("", _) | ("html", _) => HTML,
+        _ => {
+            cx.span_err(item.span,
+                        &format!("`#[template_fragment(..)]` got an unknown namespace prefix in \
+                                  context {:?}, expected html, svg or math",
+                                 &**context));
+            return Err(());
+        }
+    };
+
+    Ok(QualName::new(Namespace(Atom::from(ns)), Atom::from(local)))
+}
+
+fn get_file(cx: &ExtCtxt, item: &Item, annotation: &Annotation) -> Result<Template, ()> {
     let filename = cx.filename.clone().unwrap();
     let filename = Path::new(&filename);
 
-    let file = match get_file_argument(&annotation) {
+    let (file, span) = match get_file_argument(&annotation) {
         Some(file) => file,
         None => {
             cx.span_err(item.span,
@@ -59,17 +112,9 @@ fn get_file(cx: &ExtCtxt, item: &Item, annotation: &Annotation) -> Result<Rc<Str
     let filename = filename.parent().unwrap().join(Path::new(&**file));
 
     match cx.codemap().load_file(&filename) {
-        Ok(ref file) => {
-            let s = file.src.as_ref().unwrap().clone();
-
-            if annotation.has_flag("trim") {
-                Ok(Rc::new(s.trim().into()))
-            } else {
-                Ok(s)
-            }
-        }
+        Ok(file) => Ok(Template::new(file, annotation.has_flag("trim"), item.span)),
         Err(err) => {
-            cx.span_err(item.span,
+            cx.span_err(span,
                         &format!("`#[template_document(..)]` gave an error when opening {:?}: \
                                   {:?}",
                                  filename,
@@ -95,20 +140,25 @@ fn expand_derive_document_template(cx: &mut ExtCtxt,
 
     let builder = aster::AstBuilder::new().span(span);
 
-    if let Ok(item) = build_document_template_item(&cx, &builder, &item) {
-        push(Annotatable::Item(item));
+    if let Ok(items) = build_document_template_item(&cx, &builder, &item) {
+        for item in items {
+            push(Annotatable::Item(item));
+        }
     }
 }
 
 fn build_document_template_item(cx: &ExtCtxt,
                                 builder: &aster::AstBuilder,
                                 item: &Item)
-                                -> Result<P<Item>, ()> {
+                                -> Result<Vec<P<Item>>, ()> {
     let annotation = Annotation::new(item, "template_document");
 
-    let file = try!(get_file(cx, item, &annotation));
+    let template = try!(get_file(cx, item, &annotation));
+
+    let document = Document::parse_str_with_errors(&template.source).output.handle();
+    document.normalize();
+    resolve_assets(cx, &template, &[document.clone()]);
 
-    let document = Document::parse_str(&file).handle();
     let generics = match item.node {
         ItemKind::Struct(_, ref generics) => generics,
         _ => {
@@ -135,9 +185,12 @@ fn build_document_template_item(cx: &ExtCtxt,
 
     let where_clause = &impl_generics.where_clause;
 
-    let document_expr = document_expression(cx, builder, &document);
+    let document_expr = document_expression(cx, builder, &template, &document);
+    let mut fields = Vec::new();
+    let size = document_size_hint(&document, &mut fields);
+    let size_hint = size_hint_expr(cx, builder, size, &fields);
 
-    Ok(quote_item!(cx,
+    let mut items = vec![quote_item!(cx,
             impl $impl_generics ::borealis::serializer::SerializeDocument for $ty
                 $where_clause
             {
@@ -146,8 +199,27 @@ fn build_document_template_item(cx: &ExtCtxt,
                 {
                     $document_expr
                 }
+
+                fn size_hint(&self) -> usize {
+                    $size_hint
+                }
+
+                fn last_size(&self)
+                    -> Option<&'static ::std::thread::LocalKey<::std::cell::Cell<usize>>>
+                {
+                    thread_local!(static LAST_SIZE: ::std::cell::Cell<usize> =
+                        ::std::cell::Cell::new(0));
+                    Some(&LAST_SIZE)
+                }
             })
-           .unwrap())
+           .unwrap()];
+
+    if annotation.has_flag("csp_hashes") {
+        let hashes = inline_hashes(cx, &template, &[document]);
+        items.push(csp_hashes_item(cx, builder, item, generics, &hashes));
+    }
+
+    Ok(items)
 }
 
 fn expand_derive_fragment_template(cx: &mut ExtCtxt,
@@ -166,20 +238,32 @@ fn expand_derive_fragment_template(cx: &mut ExtCtxt,
 
     let builder = aster::AstBuilder::new().span(span);
 
-    if let Ok(item) = build_fragment_template_item(&cx, &builder, &item) {
-        push(Annotatable::Item(item));
+    if let Ok(items) = build_fragment_template_item(&cx, &builder, &item) {
+        for item in items {
+            push(Annotatable::Item(item));
+        }
     }
 }
 
 fn build_fragment_template_item(cx: &ExtCtxt,
                                 builder: &aster::AstBuilder,
                                 item: &Item)
-                                -> Result<P<Item>, ()> {
+                                -> Result<Vec<P<Item>>, ()> {
     let annotation = Annotation::new(item, "template_fragment");
 
-    let file = try!(get_file(cx, item, &annotation));
+    let template = try!(get_file(cx, item, &annotation));
+    let context = try!(get_context(cx, item, &annotation));
+
+    let handles = Fragment::parse_str_with_errors_in_context(&template.source, context)
+                      .output
+                      .handles();
+
+    for handle in handles.iter() {
+        handle.normalize();
+    }
+
+    resolve_assets(cx, &template, &handles);
 
-    let handles = Fragment::parse_str(&file).handles();
     let generics = match item.node {
         ItemKind::Struct(_, ref generics) => generics,
         _ => {
@@ -205,9 +289,12 @@ fn build_fragment_template_item(cx: &ExtCtxt,
                     .build();
 
     let where_clause = &impl_generics.where_clause;
-    let exprs: Vec<_> = handles.iter().map(|e| node_expression(cx, builder, e)).collect();
+    let exprs = nodes_expressions(cx, builder, &template, &handles, false);
+    let mut fields = Vec::new();
+    let size = nodes_size_hint(&handles, &mut fields);
+    let size_hint = size_hint_expr(cx, builder, size, &fields);
 
-    Ok(quote_item!(cx,
+    let mut items = vec![quote_item!(cx,
             impl $impl_generics ::borealis::serializer::SerializeNode for $ty
                 $where_clause
             {
@@ -216,9 +303,20 @@ fn build_fragment_template_item(cx: &ExtCtxt,
                 {
                     $exprs
                 }
+
+                fn size_hint(&self) -> usize {
+                    $size_hint
+                }
             }
         )
-           .unwrap())
+           .unwrap()];
+
+    if annotation.has_flag("csp_hashes") {
+        let hashes = inline_hashes(cx, &template, &handles);
+        items.push(csp_hashes_item(cx, builder, item, generics, &hashes));
+    }
+
+    Ok(items)
 }
 
 #[plugin_registrar]
@@ -0,0 +1,233 @@
+
+use std::ascii::AsciiExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use borealis::dom::Handle;
+
+use syntax::ast::Expr;
+use syntax::codemap::{BytePos, FileMap, Span, NO_EXPANSION};
+use syntax::ext::base::{DummyResult, ExtCtxt};
+use syntax::ext::quote::rt::ExtParseUtils;
+use syntax::parse::{self, token};
+use syntax::ptr::P;
+
+// A template file loaded into the codemap, so that errors can point into it.
+pub struct Template {
+    pub source: Rc<String>,
+    filemap: Rc<FileMap>,
+    // The number of bytes trimmed from the start of the file.
+    offset: usize,
+    item_span: Span,
+}
+
+impl Template {
+    pub fn new(filemap: Rc<FileMap>, trim: bool, item_span: Span) -> Template {
+        let source = filemap.src.as_ref().unwrap().clone();
+
+        let (source, offset) = if trim {
+            let trimmed = source.trim_left();
+            let offset = source.len() - trimmed.len();
+            (Rc::new(trimmed.trim_right().to_owned()), offset)
+        } else {
+            (source, 0)
+        };
+
+        Template {
+            source: source,
+            filemap: filemap,
+            offset: offset,
+            item_span: item_span,
+        }
+    }
+
+    // The directory of the template file, which the paths of assets are relative to.
+    pub fn dir(&self) -> PathBuf {
+        Path::new(&self.filemap.name).parent().map_or_else(PathBuf::new, |p| p.to_owned())
+    }
+
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let lo = self.filemap.start_pos.0 + (self.offset + start) as u32;
+
+        Span {
+            lo: BytePos(lo),
+            hi: BytePos(lo + (end - start) as u32),
+            expn_id: NO_EXPANSION,
+        }
+    }
+
+    // Finds `snippet` in the source, starting where `node` does. Text in the DOM doesn't always
+    // match the source byte for byte, because of character references for example, so the
+    // snippet is looked up instead of computing its offset from the node's position.
+    pub fn snippet_span(&self, node: &Handle, snippet: &str) -> Span {
+        let start = node.position().and_then(|p| {
+            self.source[p.offset..].find(snippet).map(|i| p.offset + i)
+        });
+
+        match start {
+            Some(start) => self.span(start, start + snippet.len()),
+            None => self.item_span,
+        }
+    }
+
+    // Like `snippet_span`, but looks for `snippet` from the attribute `name` to the end of the
+    // start tag of `element`, so that it can't point at another attribute or at a child.
+    pub fn attribute_span(&self, element: &Handle, name: &str, snippet: &str) -> Span {
+        let start = element.position().and_then(|p| {
+            let tag = &self.source[p.offset..start_tag_end(&self.source, p.offset)];
+            let name = attribute_offset(tag, name).unwrap_or(0);
+
+            tag[name..].find(snippet).map(|i| p.offset + name + i)
+        });
+
+        match start {
+            Some(start) => self.span(start, start + snippet.len()),
+            None => self.item_span,
+        }
+    }
+
+    pub fn span_err(&self, cx: &ExtCtxt, span: Span, message: &str) {
+        cx.struct_span_err(span, message)
+          .span_note(self.item_span, "in the template of this item")
+          .emit();
+    }
+
+    // Parses the expression of the interpolation `{{ expr }}` found at `span`. The expression is
+    // parsed from a file padded so that it lines up with the template, which makes any error in
+    // it, and any error in the code it expands to, point at the template.
+    pub fn parse_expr(&self, cx: &ExtCtxt, span: Span, expr: &str) -> P<Expr> {
+        if span == self.item_span {
+            return cx.parse_expr(expr.to_owned());
+        }
+
+        let loc = cx.codemap().lookup_char_pos(span.lo + BytePos(2));
+        let mut source = String::with_capacity(loc.line + loc.col.0 + expr.len());
+
+        for _ in 1..loc.line {
+            source.push('\n');
+        }
+
+        for _ in 0..loc.col.0 {
+            source.push(' ');
+        }
+
+        source.push_str(expr);
+
+        let filemap = cx.codemap().new_filemap(self.filemap.name.clone(), source);
+        let mut parser = parse::filemap_to_parser(cx.parse_sess(), filemap, cx.cfg());
+
+        match parser.parse_expr() {
+            Ok(expr) => {
+                if parser.token != token::Eof {
+                    self.span_err(cx, parser.span, "expected the end of the interpolation");
+                }
+
+                expr
+            }
+            Err(mut err) => {
+                err.span_note(self.item_span, "in the template of this item");
+                err.emit();
+
+                DummyResult::raw_expr(span)
+            }
+        }
+    }
+}
+
+// The offset just past the `>` ending the start tag at `start`, skipping quoted values.
+fn start_tag_end(source: &str, start: usize) -> usize {
+    let mut quote = None;
+
+    for (i, c) in source[start..].char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return start + i + 1,
+            _ => {}
+        }
+    }
+
+    source.len()
+}
+
+// The offset of the attribute `name` in a start tag, names in the source may be in any case.
+fn attribute_offset(tag: &str, name: &str) -> Option<usize> {
+    let tag = tag.to_ascii_lowercase();
+    let mut offset = 0;
+
+    while let Some(i) = tag[offset..].find(name) {
+        let start = offset + i;
+        let end = start + name.len();
+        let before = tag[..start].chars().next_back().map_or(false, |c| c.is_whitespace());
+        let after = tag[end..].chars().next().map_or(false, |c| {
+            c.is_whitespace() || c == '=' || c == '>' || c == '/'
+        });
+
+        if before && after {
+            return Some(start);
+        }
+
+        offset = end;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+
+    use borealis::dom::{Handle, Node};
+    use borealis::Fragment;
+
+    use syntax::codemap::{BytePos, CodeMap, DUMMY_SP};
+
+    #[test]
+    fn test_snippet_span() {
+        let codemap = CodeMap::new();
+        codemap.new_filemap("other.html".to_owned(), "<p></p>".to_owned());
+        let filemap = codemap.new_filemap("test.html".to_owned(),
+                                          "\n  <p>a &amp; {{ b }}</p>\n".to_owned());
+
+        for &trim in [false, true].iter() {
+            let template = Template::new(filemap.clone(), trim, DUMMY_SP);
+            let fragment = Fragment::parse_str_with_errors(&template.source).output;
+            let p = fragment.select_first("p").unwrap().unwrap();
+            let text = p.children().next().unwrap();
+
+            let span = template.snippet_span(&text, "{{ b }}");
+            assert_eq!(span.lo, filemap.start_pos + BytePos(14));
+            assert_eq!(span.hi, filemap.start_pos + BytePos(21));
+            assert_eq!(codemap.span_to_snippet(span).unwrap(), "{{ b }}");
+        }
+    }
+
+    #[test]
+    fn test_attribute_span() {
+        let codemap = CodeMap::new();
+        let source = "<p title=\"{{ a }}>\" ID='{{ a }}'>{{ a }}</p>";
+        let filemap = codemap.new_filemap("test.html".to_owned(), source.to_owned());
+        let template = Template::new(filemap.clone(), false, DUMMY_SP);
+        let fragment = Fragment::parse_str_with_errors(&template.source).output;
+        let p = fragment.select_first("p").unwrap().unwrap();
+
+        let span = template.attribute_span(&p, "id", "{{ a }}");
+        assert_eq!(span.lo, filemap.start_pos + BytePos(24));
+        assert_eq!(codemap.span_to_snippet(span).unwrap(), "{{ a }}");
+
+        let span = template.attribute_span(&p, "title", "{{ a }}");
+        assert_eq!(span.lo, filemap.start_pos + BytePos(10));
+
+        assert_eq!(template.attribute_span(&p, "id", "</p>"), DUMMY_SP);
+    }
+
+    #[test]
+    fn test_snippet_span_without_position() {
+        let codemap = CodeMap::new();
+        let filemap = codemap.new_filemap("test.html".to_owned(), "{{ a }}".to_owned());
+        let template = Template::new(filemap, false, DUMMY_SP);
+        let text: Handle = Node::Text("{{ a }}".into()).into();
+
+        assert_eq!(template.snippet_span(&text, "{{ a }}"), DUMMY_SP);
+    }
+}
@@ -3,12 +3,14 @@
 
 extern crate borealis;
 
-use std::io::Read;
+use std::io::{Read, Write};
 use std::fs::File;
 use std::path::Path;
 
 use borealis::Document;
-use borealis::serializer::{SerializeDocument, serialize};
+use borealis::dom::diff;
+use borealis::serializer::{SerializeDocument, SerializeNode, DocumentSerializer, RenderOpts};
+use borealis::serializer::{serialize, to_string_adaptive, to_string_with_opts};
 
 #[template_document(file="test_template.html")]
 struct TestTemplate {
@@ -33,6 +35,93 @@ fn test_test_template() {
     test_document(template, "test_template", false);
 }
 
+#[test]
+fn test_rewrite_urls() {
+    let template = TestTemplate {
+        value: "Test".to_owned(),
+        fragment: TestFragment {
+            value: 10,
+        }
+    };
+
+    let rewrite = |url: &str| format!("/static/{}", url);
+    let opts = RenderOpts { rewrite_url: Some(&rewrite), ..RenderOpts::default() };
+
+    assert_same_document(&to_string_with_opts(template, &opts).unwrap(),
+                         &read_file("tests/test_template_expected.html")
+                              .trim()
+                              .replace("src=\"img.png\"", "src=\"/static/img.png\""));
+}
+
+#[template_document(file="csp.html", csp_hashes)]
+struct CspTemplate {
+    value: String,
+}
+
+#[test]
+fn test_csp() {
+    let template = CspTemplate {
+        value: "Test".to_owned(),
+    };
+
+    let opts = RenderOpts { nonce: Some("abc"), ..RenderOpts::default() };
+
+    assert_same_document(&to_string_with_opts(template, &opts).unwrap(),
+                         read_file("tests/csp_expected.html").trim());
+    assert_eq!(CspTemplate::csp_hashes(),
+               &["sha256-pckGv9YvNcB5xy+Y4fbqhyo+ib850wyiuWeNbZvLi00=",
+                 "sha256-I+aF1T6GxfsV/3ftFVsOQOwl0AH9185nnmbxwNt269E="]);
+}
+
+#[template_document(file="assets.html")]
+struct AssetsTemplate;
+
+#[test]
+fn test_assets() {
+    test_document(AssetsTemplate, "assets", false);
+}
+
+#[test]
+fn test_size_hint() {
+    assert_eq!(EmptyTemplate.size_hint(),
+               read_file("tests/empty_expected.html").trim().len());
+    assert_eq!(StaticTemplate.size_hint(),
+               read_file("tests/static_expected.html").trim().len());
+    assert_eq!(LegacyDoctypeTemplate.size_hint(),
+               read_file("tests/legacy_doctype_expected.html").trim().len());
+    assert_eq!(BorrowedTemplate { value: "abc" }.size_hint(),
+               read_file("tests/borrowed_expected.html").trim().len());
+}
+
+#[template_document(file="borrowed.html")]
+struct BorrowedTemplate<'a> {
+    value: &'a str,
+}
+
+#[test]
+fn test_borrowed_template() {
+    let value = "abc".to_owned();
+
+    assert_same_document(&to_string_adaptive(BorrowedTemplate { value: &value }).unwrap(),
+                         read_file("tests/borrowed_expected.html").trim());
+    assert!(BorrowedTemplate { value: &value }.last_size().unwrap().with(|s| s.get()) > 0);
+}
+
+#[template_fragment(file="table_rows.html", trim, context="tbody")]
+struct TableRowsFragment {
+    values: Vec<i32>,
+}
+
+#[test]
+fn test_table_rows_fragment() {
+    let fragment = TableRowsFragment {
+        values: vec![1, 2],
+    };
+
+    assert_same_document(&serialize_doc(FragmentDocument(fragment)),
+                         read_file("tests/table_rows_expected.html").trim());
+}
+
 #[template_document(file="empty.html")]
 struct EmptyTemplate;
 
@@ -49,6 +138,30 @@ fn test_doctype_template() {
     test_document(DoctypeTemplate, "doctype", true);
 }
 
+#[template_document(file="legacy_doctype.html")]
+struct LegacyDoctypeTemplate;
+
+#[test]
+fn test_legacy_doctype_template() {
+    test_document(LegacyDoctypeTemplate, "legacy_doctype", true);
+}
+
+#[template_document(file="comments.html")]
+struct CommentsTemplate;
+
+#[test]
+fn test_comments_template() {
+    test_document(CommentsTemplate, "comments", true);
+}
+
+#[template_document(file="template_element.html")]
+struct TemplateElementTemplate;
+
+#[test]
+fn test_template_element_template() {
+    test_document(TemplateElementTemplate, "template_element", false);
+}
+
 #[template_document(file="element.html")]
 struct ElementTemplate;
 
@@ -57,15 +170,72 @@ fn test_element_template() {
     test_document(ElementTemplate, "element", true);
 }
 
+#[template_document(file="static.html")]
+struct StaticTemplate;
+
+#[test]
+fn test_static_template() {
+    test_document(StaticTemplate, "static", true);
+}
+
+#[test]
+fn test_static_template_with_opts() {
+    let rewrite = |url: &str| format!("/static/{}", url);
+    let opts = RenderOpts { rewrite_url: Some(&rewrite), nonce: Some("abc") };
+
+    assert_same_document(&to_string_with_opts(StaticTemplate, &opts).unwrap(),
+                         &read_file("tests/static_expected.html")
+                              .trim()
+                              .replace("src=\"img.png\"", "src=\"/static/img.png\"")
+                              .replace("<style>", "<style nonce=\"abc\">")
+                              .replace("<script>", "<script nonce=\"abc\">"));
+}
+
+#[template_document(file="custom_element.html")]
+struct CustomElementTemplate;
+
+#[test]
+fn test_custom_element_template() {
+    test_document(CustomElementTemplate, "custom_element", false);
+}
+
+#[template_document(file="interpolation.html")]
+struct InterpolationTemplate {
+    a: bool,
+    b: bool,
+}
+
+#[test]
+fn test_interpolation_template() {
+    test_document(InterpolationTemplate { a: true, b: true }, "interpolation", false);
+}
+
 fn test_document<T: SerializeDocument>(document: T, file: &str, c: bool) {
     let document_a = serialize_doc(document);
     let document_b = read_file(format!("tests/{}_expected.html", file));
     let document_c = serialize_doc(read_document(format!("tests/{}.html", file)));
 
-    assert_eq!(document_a, document_b.trim());
+    assert_same_document(&document_a, document_b.trim());
 
     if c {
-        assert_eq!(document_c, document_b.trim());
+        assert_same_document(&document_c, document_b.trim());
+    }
+}
+
+// Compares serialized documents, reporting how their trees differ when they don't match.
+fn assert_same_document(a: &str, b: &str) {
+    if a != b {
+        let diff = diff(&Document::parse_str(a).handle(), &Document::parse_str(b).handle());
+        panic!("documents differ:\n{}\n\nleft:  {}\nright: {}", diff, a, b);
+    }
+}
+
+struct FragmentDocument<T: SerializeNode>(T);
+
+impl<T: SerializeNode> SerializeDocument for FragmentDocument<T> {
+    fn serialize_document<W: Write>(self, s: DocumentSerializer<W>) {
+        let mut s = s.node();
+        self.0.serialize_node(&mut s);
     }
 }
 